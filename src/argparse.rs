@@ -1,15 +1,122 @@
 #[derive(argh::FromArgs, Debug)]
 /// WIP
+#[argh(
+    error_code(1, "unexpected error"),
+    error_code(2, "findings on --fail-on rules (with --baseline: only new ones)"),
+    error_code(3, "files failed to probe"),
+    error_code(4, "cache database error"),
+    error_code(5, "invalid config, baseline or rule list"),
+    error_code(130, "interrupted")
+)]
 pub struct Args {
     #[argh(positional)]
-    /// path to folder which gets parsed
-    pub path: String,
+    /// folders which get parsed, shorthand for `jwatch scan <path>...`
+    pub path: Vec<String>,
 
     #[argh(option)]
-    /// path to cache database
+    /// path to cache database, defaults to jwatch.sqlite inside the first folder
     pub db_path: Option<String>,
 
     #[argh(option, short = 'j', default = "2")]
     /// number of parallel mediainfo probes
     pub jobs: usize,
+
+    #[argh(option, default = "8")]
+    /// number of threads listing folders, probing starts while they still run
+    pub walk_jobs: usize,
+
+    #[argh(option)]
+    /// config file, defaults to jwatch.toml inside the scanned folder if present
+    pub config: Option<String>,
+
+    #[argh(option)]
+    /// comma-separated rules whose findings fail the run, or "all"/"none" (default: all)
+    pub fail_on: Option<String>,
+
+    #[argh(option)]
+    /// only report and fail on findings missing from this baseline file
+    pub baseline: Option<String>,
+
+    #[argh(option)]
+    /// snapshot the current findings into this baseline file
+    pub write_baseline: Option<String>,
+
+    #[argh(switch)]
+    /// guess the language of untagged text subtitles from their content (needs ffmpeg)
+    pub detect_subtitle_language: bool,
+
+    #[argh(switch)]
+    /// print mkvpropedit commands tagging the guessed subtitle languages, implies --detect-subtitle-language
+    pub suggest_tagging: bool,
+
+    #[argh(option)]
+    /// skip paths matching this glob, gitignore syntax like .jwatchignore files (repeatable)
+    pub exclude: Vec<String>,
+
+    #[argh(option, default = "60")]
+    /// skip files modified less than this many seconds ago as still being written, 0 to probe them anyway
+    pub settle_secs: u64,
+
+    #[argh(switch)]
+    /// list every folder and stat every file, even those unchanged since the last scan
+    pub full_scan: bool,
+
+    #[argh(option, default = "String::from(\"newest\")")]
+    /// which files to probe first within new, modified, stale and failed ones: newest, largest or path (default: newest)
+    pub order: String,
+
+    #[argh(option)]
+    /// only probe this many of the walked files, or this percentage like 5%, and estimate the summary for all of them
+    pub sample: Option<String>,
+
+    #[argh(option, default = "0")]
+    /// seed picking the --sample, the same one picks the same files again
+    pub sample_seed: u64,
+
+    #[argh(switch)]
+    /// also detect videos by their first bytes, and report extensions not matching the container
+    pub sniff: bool,
+
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(argh::FromArgs, Debug)]
+#[argh(subcommand)]
+pub enum Command {
+    Scan(ScanArgs),
+    Status(StatusArgs),
+    Watch(WatchArgs),
+}
+
+#[derive(argh::FromArgs, Debug)]
+#[argh(subcommand, name = "scan")]
+/// probe all media and report non-ideal files
+pub struct ScanArgs {
+    #[argh(positional)]
+    /// folders which get parsed, defaults to `roots` from the config
+    pub path: Vec<String>,
+
+    #[argh(switch)]
+    /// continue where the last interrupted scan of the same folders stopped
+    pub resume: bool,
+}
+
+#[derive(argh::FromArgs, Debug)]
+#[argh(subcommand, name = "status")]
+/// compare the folder against the cache: new, modified and removed files, and changed findings
+pub struct StatusArgs {
+    #[argh(positional)]
+    /// folders which get compared, defaults to `roots` from the config
+    pub path: Vec<String>,
+}
+
+#[derive(argh::FromArgs, Debug)]
+#[argh(subcommand, name = "watch")]
+/// scan, then keep running and report the findings of new and modified videos as they arrive.
+/// SIGHUP reloads the config, SIGTERM or CTRL+C stops.
+pub struct WatchArgs {
+    #[argh(positional)]
+    /// folders which get watched, defaults to `roots` from the config
+    pub path: Vec<String>,
 }
@@ -0,0 +1,42 @@
+use crate::JwatchResult;
+use crate::rules::Rule;
+use color_eyre::eyre::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// Snapshot of known findings, so CI-style runs only fail on newly introduced ones.
+/// Keyed by full path, the same however many folders are scanned, then rule name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    findings: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Baseline {
+    pub fn load(file: &Path) -> JwatchResult<Self> {
+        let json = fs::read_to_string(file)
+            .with_context(|| format!("failed to read baseline {}", file.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse baseline {}", file.display()))
+    }
+
+    pub fn write(&self, file: &Path) -> JwatchResult<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(file, json)
+            .with_context(|| format!("failed to write baseline {}", file.display()))
+    }
+
+    pub fn insert(&mut self, path: &str, rule: Rule) {
+        self.findings
+            .entry(path.to_owned())
+            .or_default()
+            .insert(rule.name().to_owned());
+    }
+
+    pub fn contains(&self, path: &str, rule: Rule) -> bool {
+        self.findings
+            .get(path)
+            .is_some_and(|rules| rules.contains(rule.name()))
+    }
+}
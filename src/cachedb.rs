@@ -1,13 +1,17 @@
 use crate::JwatchResult;
 use crate::metastructs::Codec;
 use crate::metastructs::{LangTrack, MediaInfo};
+use crate::resume::ScanState;
+use crate::rules::{Finding, Rule};
+use crate::schedule::Failure;
+use crate::walk::{DirEntry, DirListing};
 use color_eyre::eyre::{Context, ContextCompat, bail};
 use rusqlite::Connection;
 use std::cell::Cell;
 use std::collections::HashMap;
 use std::fs;
 use std::hash::{DefaultHasher, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
@@ -16,27 +20,18 @@ use time::OffsetDateTime;
 const DB_APP_ID: i32 = i32::from_le_bytes([b'j', b'w', b'a', b't']);
 /// Stores are grouped into transactions of this many INSERTs to avoid a commit+fsync per file
 const STORE_BATCH_SIZE: u32 = 64;
+/// Bump when probing starts collecting data old rows lack, so the cache is rebuilt like on a schema change
+const PROBE_VERSION: u32 = 3;
 
-/// Space-separated `lang:size` pairs, e.g. "en:123456 fr:0"
+/// Tracks are stored as a JSON array of [`LangTrack`]
 fn serialize_lang_tracks(tracks: &[LangTrack]) -> String {
-    tracks
-        .iter()
-        .map(|t| format!("{}:{}", t.language, t.size))
-        .collect::<Vec<_>>()
-        .join(" ")
+    serde_json::to_string(tracks).expect("LangTrack always serializes")
 }
 
-fn parse_lang_tracks(s: &str) -> Vec<LangTrack> {
-    s.split(' ')
-        .filter(|p| !p.is_empty())
-        .map(|p| {
-            let (language, size) = p.rsplit_once(':').unwrap_or((p, "0"));
-            LangTrack {
-                language: language.to_owned(),
-                size: size.parse().unwrap_or(0),
-            }
-        })
-        .collect()
+fn parse_lang_tracks(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Vec<LangTrack>> {
+    serde_json::from_str(&row.get::<_, String>(idx)?).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
 }
 
 #[derive(Clone)]
@@ -56,7 +51,7 @@ impl CacheDB {
             connection.pragma_query_value(None, "schema_version", |row| row.get(0))?;
         if db_app_id != DB_APP_ID && schema_version != 0 {
             // Schema 0 means the DB is uninitialized
-            panic!(
+            bail!(
                 "Database app ID mismatch, refusing to touch it\nIf you're confident it is the correct one, you can manually delete it at {}",
                 db_file.display()
             );
@@ -65,7 +60,8 @@ impl CacheDB {
         let dbschema = //language=sqlite
             "\
 	CREATE TABLE IF NOT EXISTS media (
-	path TEXT PRIMARY KEY,
+	path TEXT PRIMARY KEY, -- full path, file names repeat across folders and roots
+	root TEXT NOT NULL, -- scanned folder the file was found in
 	duration INTEGER NOT NULL,
 	size INTEGER NOT NULL,
 	bitrate INTEGER NOT NULL,
@@ -74,12 +70,36 @@ impl CacheDB {
 	codec TEXT NOT NULL,
     last_checked INTEGER NOT NULL,
     mtime INTEGER NOT NULL,
-    audio_tracks TEXT NOT NULL,
-    subtitle_tracks TEXT NOT NULL,
-    whitelisted BOOLEAN NOT NULL
-	)";
+    audio_tracks TEXT NOT NULL, -- JSON
+    subtitle_tracks TEXT NOT NULL, -- JSON
+    whitelisted BOOLEAN NOT NULL,
+    disc TEXT -- JSON, NULL unless a disc folder
+	);
+	CREATE TABLE IF NOT EXISTS findings (
+	path TEXT NOT NULL, -- full path
+	rule TEXT NOT NULL,
+	reason TEXT NOT NULL,
+	PRIMARY KEY (path, rule)
+	);
+	CREATE TABLE IF NOT EXISTS dirs (
+	path TEXT PRIMARY KEY,
+	mtime INTEGER NOT NULL, -- nanoseconds
+	verified INTEGER NOT NULL, -- last listed from disk
+	entries TEXT NOT NULL -- JSON [[name, is_dir]]
+	);
+	CREATE TABLE IF NOT EXISTS scan_state (
+	id INTEGER PRIMARY KEY CHECK (id = 0), -- at most one interrupted scan
+	state TEXT NOT NULL -- JSON
+	);
+	CREATE TABLE IF NOT EXISTS failures (
+	path TEXT PRIMARY KEY, -- full path
+	mtime INTEGER NOT NULL, -- as when it failed
+	size INTEGER NOT NULL,
+	error TEXT NOT NULL
+	);";
         let mut hasher = DefaultHasher::new();
         hasher.write(dbschema.as_bytes());
+        hasher.write_u32(PROBE_VERSION);
         let hash = hasher.finish() as i32; // Yes this truncates a bit, doesn't matter though.
         let dbhash: i32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
 
@@ -96,29 +116,31 @@ impl CacheDB {
                 .context("failed to close cachedb while migrating")?;
             fs::remove_file(db_file)?;
             connection = Connection::open(db_file)?;
-            connection.pragma_update(None, "application_id", &DB_APP_ID)?;
+            connection.pragma_update(None, "application_id", DB_APP_ID)?;
         }
-        connection.pragma_update(None, "user_version", &hash)?;
+        connection.pragma_update(None, "user_version", hash)?;
 
-        connection.execute(dbschema, ())?;
+        connection.execute_batch(dbschema)?;
 
         // journal_mode returns a result row, so plain pragma_update would fail
         connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
         connection.pragma_update(None, "synchronous", "NORMAL")?;
 
         Ok(Self {
+            // Arc only for the try_unwrap in cleanup; the connection never leaves the main thread
+            #[allow(clippy::arc_with_non_send_sync)]
             connection: Arc::new(connection),
             pending_stores: Cell::new(0),
         })
     }
 
-    /// Loads the entire cache, keyed by filename. Worker threads cannot touch the
+    /// Loads the entire cache, keyed by full path. Worker threads cannot touch the
     /// (!Sync) connection, so lookups run against this in-memory snapshot instead.
-    pub fn load_all(&self) -> JwatchResult<HashMap<String, MediaInfo>> {
+    pub fn load_all(&self) -> JwatchResult<HashMap<PathBuf, MediaInfo>> {
         let mut stmt = self.connection.prepare(
             //language=sqlite
             "
-		SELECT path, duration, size, bitrate, height, width, codec, last_checked, mtime, audio_tracks, subtitle_tracks, whitelisted
+		SELECT path, duration, size, bitrate, height, width, codec, last_checked, mtime, audio_tracks, subtitle_tracks, whitelisted, disc
 		FROM media
 	",
         )?;
@@ -134,42 +156,50 @@ impl CacheDB {
                     codec: Codec::from_str(row.get_ref(6)?.as_str()?),
                     last_checked: OffsetDateTime::from_unix_timestamp(row.get(7)?).unwrap(),
                     mtime: row.get(8)?,
-                    audio_language: parse_lang_tracks(&row.get::<_, String>(9)?),
-                    subtitle_languages: parse_lang_tracks(&row.get::<_, String>(10)?),
+                    audio_language: parse_lang_tracks(row, 9)?,
+                    subtitle_languages: parse_lang_tracks(row, 10)?,
                     whitelisted: row.get(11)?,
+                    container: None,
+                    disc: row
+                        .get::<_, Option<String>>(12)?
+                        .map(|json| serde_json::from_str(&json))
+                        .transpose()
+                        .map_err(|e| {
+                            rusqlite::Error::FromSqlConversionFailure(
+                                12,
+                                rusqlite::types::Type::Text,
+                                Box::new(e),
+                            )
+                        })?,
                 },
             ))
         })?;
 
         let mut map = HashMap::new();
         for row in rows {
-            let (filename, info) = row?;
-            map.insert(filename, info);
+            let (path, info) = row?;
+            map.insert(PathBuf::from(path), info);
         }
         Ok(map)
     }
 
+    /// `root` is the scanned folder the file was found in
     pub fn store_to_cachedb(
         &self,
         p: impl AsRef<Path>,
+        root: &Path,
         media_info: &MediaInfo,
     ) -> JwatchResult<()> {
-        if self.connection.is_autocommit() {
-            // Running BEGIN switches out of autocommit mode and starts the batch
-            self.connection.execute_batch("BEGIN")?;
-        }
+        self.begin_batch()?;
         self.connection.execute(
             //language=sqlite
             "\
 	INSERT OR REPLACE INTO media
-	(path, duration, size, bitrate, height, width, codec, last_checked, mtime, audio_tracks, subtitle_tracks, whitelisted)
-	VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+	(path, duration, size, bitrate, height, width, codec, last_checked, mtime, audio_tracks, subtitle_tracks, whitelisted, disc, root)
+	VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
 	",
             (
-                p.as_ref()
-                    .file_name()
-                    .context("missing filename")?
-                    .to_string_lossy(),
+                p.as_ref().to_string_lossy(),
                 media_info.duration.as_millis() as i64,
                 media_info.size,
                 media_info.bitrate,
@@ -181,9 +211,256 @@ impl CacheDB {
                 serialize_lang_tracks(&media_info.audio_language),
                 serialize_lang_tracks(&media_info.subtitle_languages),
                 media_info.whitelisted,
+                media_info
+                    .disc
+                    .as_ref()
+                    .map(|disc| serde_json::to_string(disc).expect("DiscInfo always serializes")),
+                root.to_string_lossy(),
+            ),
+        )?;
+        self.connection.execute(
+            //language=sqlite
+            "DELETE FROM failures WHERE path = ?1",
+            (p.as_ref().to_string_lossy(),),
+        )?;
+        self.finish_store()
+    }
+
+    /// Remembers a file that failed to probe, so the next scan retries it last
+    pub fn store_failure(
+        &self,
+        p: impl AsRef<Path>,
+        failure: Failure,
+        error: &str,
+    ) -> JwatchResult<()> {
+        self.begin_batch()?;
+        self.connection.execute(
+            //language=sqlite
+            "INSERT OR REPLACE INTO failures (path, mtime, size, error) VALUES (?1, ?2, ?3, ?4)",
+            (
+                p.as_ref().to_string_lossy(),
+                failure.mtime,
+                failure.size as i64,
+                error,
+            ),
+        )?;
+        self.finish_store()
+    }
+
+    /// Files that failed to probe, keyed by path like [`Self::load_all`]
+    pub fn load_failures(&self) -> JwatchResult<HashMap<PathBuf, Failure>> {
+        let mut stmt = self.connection.prepare(
+            //language=sqlite
+            "SELECT path, mtime, size FROM failures",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Failure {
+                    mtime: row.get(1)?,
+                    size: row.get::<_, i64>(2)? as u64,
+                },
+            ))
+        })?;
+        let mut map = HashMap::new();
+        for row in rows {
+            let (path, failure) = row?;
+            map.insert(PathBuf::from(path), failure);
+        }
+        Ok(map)
+    }
+
+    /// Replaces the findings recorded for this file by the last scan
+    pub fn store_findings(&self, p: impl AsRef<Path>, findings: &[Finding]) -> JwatchResult<()> {
+        let path = p.as_ref().to_string_lossy();
+        self.begin_batch()?;
+        self.connection.execute(
+            //language=sqlite
+            "DELETE FROM findings WHERE path = ?1",
+            (&path,),
+        )?;
+        for finding in findings {
+            self.connection.execute(
+                //language=sqlite
+                "INSERT OR REPLACE INTO findings (path, rule, reason) VALUES (?1, ?2, ?3)",
+                (&path, finding.rule.name(), &finding.reason),
+            )?;
+        }
+        self.finish_store()
+    }
+
+    /// Forgets a file the walk no longer finds, with its findings and any failure
+    pub fn remove(&self, p: impl AsRef<Path>) -> JwatchResult<()> {
+        let path = p.as_ref().to_string_lossy();
+        self.begin_batch()?;
+        for statement in [
+            //language=sqlite
+            "DELETE FROM media WHERE path = ?1",
+            //language=sqlite
+            "DELETE FROM findings WHERE path = ?1",
+            //language=sqlite
+            "DELETE FROM failures WHERE path = ?1",
+        ] {
+            self.connection.execute(statement, (&path,))?;
+        }
+        self.finish_store()
+    }
+
+    /// Scanned folder of every cached file, keyed by path like [`Self::load_all`]
+    pub fn load_roots(&self) -> JwatchResult<HashMap<PathBuf, PathBuf>> {
+        let mut stmt = self.connection.prepare(
+            //language=sqlite
+            "SELECT path, root FROM media",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut map = HashMap::new();
+        for row in rows {
+            let (path, root) = row?;
+            map.insert(PathBuf::from(path), PathBuf::from(root));
+        }
+        Ok(map)
+    }
+
+    /// Remembers what the directory held at its current mtime
+    pub fn store_dir(&self, dir: &Path, listing: &DirListing) -> JwatchResult<()> {
+        let entries = listing
+            .entries
+            .iter()
+            .map(|e| Ok((e.name.to_str().context("non-UTF-8 name")?, e.dir)))
+            .collect::<JwatchResult<Vec<_>>>()?;
+        self.begin_batch()?;
+        self.connection.execute(
+            //language=sqlite
+            "INSERT OR REPLACE INTO dirs (path, mtime, verified, entries) VALUES (?1, ?2, ?3, ?4)",
+            (
+                dir.to_string_lossy(),
+                listing.mtime,
+                listing.verified,
+                serde_json::to_string(&entries).expect("entries always serialize"),
             ),
         )?;
+        self.finish_store()
+    }
+
+    /// Directory listings by full path, for the walk to skip unchanged directories
+    pub fn load_dirs(&self) -> JwatchResult<HashMap<PathBuf, DirListing>> {
+        let mut stmt = self.connection.prepare(
+            //language=sqlite
+            "SELECT path, mtime, verified, entries FROM dirs",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let entries: Vec<(String, bool)> = serde_json::from_str(&row.get::<_, String>(3)?)
+                .map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        3,
+                        rusqlite::types::Type::Text,
+                        Box::new(e),
+                    )
+                })?;
+            Ok((
+                PathBuf::from(row.get::<_, String>(0)?),
+                DirListing {
+                    mtime: row.get(1)?,
+                    verified: row.get(2)?,
+                    entries: entries
+                        .into_iter()
+                        .map(|(name, dir)| DirEntry {
+                            name: name.into(),
+                            dir,
+                        })
+                        .collect(),
+                },
+            ))
+        })?;
+        let mut map = HashMap::new();
+        for row in rows {
+            let (dir, listing) = row?;
+            map.insert(dir, listing);
+        }
+        Ok(map)
+    }
+
+    /// Remembers where an interrupted scan stopped, replacing any older one
+    pub fn store_scan_state(&self, state: &ScanState) -> JwatchResult<()> {
+        self.begin_batch()?;
+        self.connection.execute(
+            //language=sqlite
+            "INSERT OR REPLACE INTO scan_state (id, state) VALUES (0, ?1)",
+            (serde_json::to_string(state)?,),
+        )?;
+        self.finish_store()
+    }
+
+    pub fn load_scan_state(&self) -> JwatchResult<Option<ScanState>> {
+        let mut stmt = self.connection.prepare(
+            //language=sqlite
+            "SELECT state FROM scan_state WHERE id = 0",
+        )?;
+        let mut rows = stmt.query([])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(serde_json::from_str(&row.get::<_, String>(0)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// After a scan that finished, there is nothing to resume
+    pub fn clear_scan_state(&self) -> JwatchResult<()> {
+        self.begin_batch()?;
+        self.connection.execute(
+            //language=sqlite
+            "DELETE FROM scan_state",
+            (),
+        )?;
+        self.finish_store()
+    }
+
+    /// Findings recorded by the last scan, keyed by path like [`Self::load_all`].
+    /// Rules this build doesn't know anymore are dropped.
+    pub fn load_findings(&self) -> JwatchResult<HashMap<PathBuf, Vec<(Rule, String)>>> {
+        let mut stmt = self.connection.prepare(
+            //language=sqlite
+            "SELECT path, rule, reason FROM findings",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+
+        let mut map: HashMap<PathBuf, Vec<(Rule, String)>> = HashMap::new();
+        for row in rows {
+            let (path, rule, reason) = row?;
+            if let Some(rule) = Rule::from_name(&rule) {
+                map.entry(PathBuf::from(path))
+                    .or_default()
+                    .push((rule, reason));
+            }
+        }
+        Ok(map)
+    }
+
+    /// Commits the pending store batch now, for long-running processes storing a file at a time
+    pub fn flush(&self) -> JwatchResult<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        self.pending_stores.set(0);
+        Ok(())
+    }
+
+    fn begin_batch(&self) -> JwatchResult<()> {
+        if self.connection.is_autocommit() {
+            // Running BEGIN switches out of autocommit mode and starts the batch
+            self.connection.execute_batch("BEGIN")?;
+        }
+        Ok(())
+    }
 
+    fn finish_store(&self) -> JwatchResult<()> {
         let pending = self.pending_stores.get() + 1;
         if pending >= STORE_BATCH_SIZE {
             self.connection.execute_batch("COMMIT")?;
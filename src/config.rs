@@ -0,0 +1,307 @@
+use crate::JwatchResult;
+use crate::language;
+use crate::rules::{Policy, Rule, UnknownTracks};
+use color_eyre::eyre::{Context, bail, eyre};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the config file picked up from the scanned folder when no `--config` is given
+pub const CONFIG_FILE_NAME: &str = "jwatch.toml";
+/// Per-directory config layered over the main one for everything below it
+pub const LAYER_FILE_NAME: &str = ".jwatch.toml";
+
+/// Marks errors of a `.jwatch.toml` found by the walk, which fail the run like an
+/// invalid main config
+#[derive(Debug)]
+pub struct InvalidLayer;
+
+impl Display for InvalidLayer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid {LAYER_FILE_NAME}")
+    }
+}
+
+/// `jwatch.toml`. Every key is optional and falls back to the built-in policy.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `[min, max]` in mbit/s
+    pub accepted_bitrate: Option<[f64; 2]>,
+    pub accepted_languages: Option<Vec<String>>,
+    /// Accepted on top of the inherited `accepted_languages`
+    pub add_accepted_languages: Option<Vec<String>>,
+    /// At least one audio track has to be in one of these
+    pub required_audio_languages: Option<Vec<String>>,
+    /// At least one subtitle track has to be in one of these
+    pub required_subtitle_languages: Option<Vec<String>>,
+    /// Rule names whose findings make the run fail, see `--fail-on`
+    pub fail_on: Option<Vec<String>>,
+    /// Never count forced subtitles as undesired, defaults to true
+    pub keep_forced_subtitles: Option<bool>,
+    /// Languages that need an SDH subtitle track
+    pub required_sdh_languages: Option<Vec<String>>,
+    /// `tolerate`, `flag` or `remove` tracks without a language tag
+    pub unknown_language_tracks: Option<UnknownTracks>,
+    /// Lossless audio tracks above this many GiB are flagged for conversion to Opus
+    pub max_lossless_audio_gib: Option<f64>,
+    /// Require an audio track with at most two channels
+    pub require_stereo: Option<bool>,
+    /// Count toward savings
+    pub remove_commentary: Option<bool>,
+    /// Rule names that report nothing
+    pub disabled_rules: Option<Vec<String>>,
+    /// Directory relative to the scanned folder -> original language of everything below it
+    pub original_languages: BTreeMap<PathBuf, String>,
+    /// In a `.jwatch.toml`: original language of everything below it, before `original_languages`
+    pub original_language: Option<String>,
+    /// Folders to scan when none are given on the command line, relative to the config file
+    pub roots: Vec<PathBuf>,
+    /// Days after which unchanged folders are listed and their files stat'ed again, default 7
+    pub full_scan_days: Option<u64>,
+}
+
+impl Config {
+    pub fn load(file: &Path) -> JwatchResult<Self> {
+        let config = Self::parse(file)?;
+        if config.original_language.is_some() {
+            bail!(
+                "{}: original_language only works in a {LAYER_FILE_NAME}, use original_languages here",
+                file.display()
+            );
+        }
+        Ok(config)
+    }
+
+    fn parse(file: &Path) -> JwatchResult<Self> {
+        let text = fs::read_to_string(file)
+            .with_context(|| format!("failed to read config {}", file.display()))?;
+        let mut config: Config = toml::from_str(&text)
+            .with_context(|| format!("failed to parse config {}", file.display()))?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for root in &mut config.roots {
+            *root = dir.join(&*root);
+        }
+        config
+            .canonicalize_languages()
+            .with_context(|| format!("invalid language in config {}", file.display()))?;
+        // Validate eagerly, so a typo is a config error before any probing happens
+        config.policy()?;
+        if let Some(fail_on) = &config.fail_on {
+            parse_rule_list(fail_on)?;
+        }
+        Ok(config)
+    }
+
+    /// A `.jwatch.toml`: like the main config, minus what only makes sense once per run
+    pub fn load_layer(file: &Path) -> JwatchResult<Self> {
+        let config = Self::parse(file).wrap_err(InvalidLayer)?;
+        if config.fail_on.is_some()
+            || !config.original_languages.is_empty()
+            || !config.roots.is_empty()
+            || config.full_scan_days.is_some()
+        {
+            return Err(eyre!(
+                "{}: fail_on, original_languages, roots and full_scan_days only work in the main config",
+                file.display()
+            )
+            .wrap_err(InvalidLayer));
+        }
+        Ok(config)
+    }
+
+    /// Languages may be given in any ISO 639 or BCP-47 form, rules compare canonical ones
+    fn canonicalize_languages(&mut self) -> JwatchResult<()> {
+        let lists = [
+            &mut self.accepted_languages,
+            &mut self.add_accepted_languages,
+            &mut self.required_audio_languages,
+            &mut self.required_subtitle_languages,
+            &mut self.required_sdh_languages,
+        ];
+        for langs in lists.into_iter().flatten() {
+            for lang in langs {
+                *lang = canonical_language(lang)?;
+            }
+        }
+        let originals = self.original_languages.values_mut();
+        for lang in originals.chain(&mut self.original_language) {
+            *lang = canonical_language(lang)?;
+        }
+        Ok(())
+    }
+
+    pub fn policy(&self) -> JwatchResult<Policy> {
+        let mut policy = Policy::default();
+        self.apply(&mut policy, None)?;
+        Ok(policy)
+    }
+
+    /// Overrides what this config sets. With a `layer` name, remembers it as the source of
+    /// the thresholds of the affected rules.
+    pub fn apply(&self, policy: &mut Policy, layer: Option<&str>) -> JwatchResult<()> {
+        let mut set = |rules: &[Rule]| {
+            if let Some(layer) = layer {
+                for rule in rules {
+                    policy.set_by.insert(*rule, layer.to_owned());
+                }
+            }
+        };
+        if self.accepted_bitrate.is_some() {
+            set(&[Rule::Bitrate]);
+        }
+        if self.accepted_languages.is_some() || self.add_accepted_languages.is_some() {
+            set(&[Rule::AudioLanguage, Rule::SubtitleLanguage]);
+        }
+        if self.required_audio_languages.is_some() {
+            set(&[Rule::MissingAudio]);
+        }
+        if self.required_subtitle_languages.is_some() {
+            set(&[Rule::MissingSubtitles]);
+        }
+        if self.keep_forced_subtitles.is_some() {
+            set(&[Rule::SubtitleLanguage]);
+        }
+        if self.required_sdh_languages.is_some() {
+            set(&[Rule::MissingSdh]);
+        }
+        if self.unknown_language_tracks.is_some() {
+            set(&[
+                Rule::AudioLanguage,
+                Rule::SubtitleLanguage,
+                Rule::UntaggedTracks,
+            ]);
+        }
+        if self.max_lossless_audio_gib.is_some() {
+            set(&[Rule::LosslessAudio]);
+        }
+        if self.require_stereo.is_some() {
+            set(&[Rule::MissingStereo]);
+        }
+        if self.remove_commentary.is_some() {
+            set(&[Rule::Commentary]);
+        }
+
+        if let Some([min, max]) = self.accepted_bitrate {
+            if min.partial_cmp(&max) != Some(Ordering::Less) {
+                bail!("accepted_bitrate: min {min} must be below max {max}");
+            }
+            policy.accepted_bitrate = min..max;
+        }
+        if let Some(langs) = &self.accepted_languages {
+            policy.accepted_langs = langs.clone();
+        }
+        if let Some(langs) = &self.add_accepted_languages {
+            policy.accepted_langs.extend(langs.iter().cloned());
+        }
+        if let Some(langs) = &self.required_audio_languages {
+            policy.required_audio_langs = langs.clone();
+        }
+        if let Some(langs) = &self.required_subtitle_languages {
+            policy.required_subtitle_langs = langs.clone();
+        }
+        if let Some(keep) = self.keep_forced_subtitles {
+            policy.keep_forced_subs = keep;
+        }
+        if let Some(langs) = &self.required_sdh_languages {
+            policy.required_sdh_langs = langs.clone();
+        }
+        if let Some(unknown) = self.unknown_language_tracks {
+            policy.unknown_tracks = unknown;
+        }
+        if let Some(gib) = self.max_lossless_audio_gib {
+            if gib.partial_cmp(&0.0) != Some(Ordering::Greater) {
+                bail!("max_lossless_audio_gib: {gib} must be positive");
+            }
+            policy.max_lossless_audio_size = Some((gib * 2.0_f64.powi(30)) as u64);
+        }
+        if let Some(require) = self.require_stereo {
+            policy.require_stereo = require;
+        }
+        if let Some(remove) = self.remove_commentary {
+            policy.remove_commentary = remove;
+        }
+        if let Some(rules) = &self.disabled_rules {
+            policy.disabled_rules = parse_rule_list(rules)?;
+        }
+        Ok(())
+    }
+}
+
+/// `.jwatch.toml` files found by the walk, by directory
+#[derive(Debug, Default)]
+pub struct PolicyLayers {
+    layers: BTreeMap<PathBuf, Config>,
+}
+
+impl PolicyLayers {
+    pub fn insert(&mut self, dir: PathBuf, config: Config) {
+        self.layers.insert(dir, config);
+    }
+
+    pub fn dirs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.layers.keys()
+    }
+
+    /// `base` with the layers of every directory above `file` applied, outermost first.
+    /// Layers are named by their path relative to `root`.
+    pub fn policy_for(&self, base: &Policy, root: &Path, file: &Path) -> Policy {
+        let mut policy = base.clone();
+        let mut layers = self
+            .layers
+            .iter()
+            .filter(|(dir, _)| file.starts_with(dir))
+            .collect::<Vec<_>>();
+        layers.sort_by_key(|(dir, _)| dir.components().count());
+        for (dir, config) in layers {
+            let name = dir.join(LAYER_FILE_NAME);
+            let name = name.strip_prefix(root).unwrap_or(&name);
+            // Validated when loaded
+            let _ = config.apply(&mut policy, Some(&name.to_string_lossy()));
+        }
+        policy
+    }
+
+    /// `original_language` of the innermost layer above `file` that sets one
+    pub fn original_language(&self, file: &Path) -> Option<&str> {
+        self.layers
+            .iter()
+            .filter(|(dir, config)| file.starts_with(dir) && config.original_language.is_some())
+            .max_by_key(|(dir, _)| dir.components().count())
+            .and_then(|(_, config)| config.original_language.as_deref())
+    }
+}
+
+fn canonical_language(lang: &str) -> JwatchResult<String> {
+    match language::canonical(lang) {
+        Some(canonical) => Ok(canonical.to_owned()),
+        None => bail!("unknown language {lang:?}"),
+    }
+}
+
+/// Rule names as given to `--fail-on` or `fail_on`; `all` and `none` are shorthands
+pub fn parse_rule_list<S: AsRef<str>>(names: &[S]) -> JwatchResult<Vec<Rule>> {
+    let mut rules = vec![];
+    for name in names {
+        match name.as_ref().trim() {
+            "all" => rules.extend_from_slice(Rule::ALL),
+            "none" | "" => {}
+            name => match Rule::from_name(name) {
+                Some(rule) => rules.push(rule),
+                None => {
+                    let known = Rule::ALL.iter().map(|r| r.name()).collect::<Vec<_>>();
+                    bail!(
+                        "unknown rule {name:?}, expected one of: all, none, {}",
+                        known.join(", ")
+                    );
+                }
+            },
+        }
+    }
+    rules.sort();
+    rules.dedup();
+    Ok(rules)
+}
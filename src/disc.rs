@@ -0,0 +1,405 @@
+use crate::JwatchResult;
+use crate::language;
+use crate::mediainfo::probe_mediainfo;
+use crate::metastructs::MediaInfo;
+use color_eyre::eyre::{ContextCompat, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// MPLS timestamps tick at 45 kHz
+const MPLS_TICKS_PER_SEC: f64 = 45_000.0;
+/// Start of the VTSI_MAT fields we read in a `VTS_nn_0.IFO`
+const IFO_AUDIO_COUNT: usize = 0x202;
+const IFO_AUDIO_ATTRS: usize = 0x204;
+const IFO_SUBPICTURE_COUNT: usize = 0x254;
+const IFO_SUBPICTURE_ATTRS: usize = 0x256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscKind {
+    Bluray,
+    Dvd,
+}
+
+impl DiscKind {
+    /// By the name of the folder holding the disc structure
+    fn from_dir_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("BDMV") {
+            Some(DiscKind::Bluray)
+        } else if name.eq_ignore_ascii_case("VIDEO_TS") {
+            Some(DiscKind::Dvd)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for DiscKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscKind::Bluray => write!(f, "Blu-ray"),
+            DiscKind::Dvd => write!(f, "DVD"),
+        }
+    }
+}
+
+/// What a disc folder title remembers about its structure, cached alongside its [`MediaInfo`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscInfo {
+    pub kind: DiscKind,
+    /// Playlist (`00800.mpls`) or title set (`VTS_01`)
+    pub main_feature: String,
+    /// Bytes of the streams the main feature plays, roughly what a remux keeps
+    pub main_feature_size: u64,
+}
+
+/// A `BDMV` or `VIDEO_TS` folder, found below a title folder
+#[derive(Debug)]
+pub struct Disc {
+    kind: DiscKind,
+    /// Everything in the disc structure: menus, extras, other angles
+    pub size: u64,
+    /// Newest modification of any file in it, seconds since the epoch
+    pub mtime: i64,
+    main_feature: String,
+    main_feature_size: u64,
+    /// Playlist length, unknown for DVDs
+    duration: Option<Duration>,
+    /// What mediainfo gets run on
+    probe_file: PathBuf,
+    /// For DVDs, languages live in the IFO rather than the VOBs
+    ifo: Option<PathBuf>,
+}
+
+/// The title folder of a disc structure, if `dir` is the `BDMV`/`VIDEO_TS` folder of one
+pub fn title_dir(dir: &Path) -> Option<&Path> {
+    let kind = DiscKind::from_dir_name(&dir.file_name()?.to_string_lossy())?;
+    let marker = match kind {
+        DiscKind::Bluray => "STREAM",
+        DiscKind::Dvd => "VIDEO_TS.IFO",
+    };
+    find_child(dir, marker)?;
+    dir.parent()
+}
+
+/// Case-insensitive lookup of a direct child, discs come in upper and lower case
+fn find_child(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .find(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().eq_ignore_ascii_case(name))
+        })
+}
+
+impl Disc {
+    /// Reads the disc structure inside `title`, `None` if there is none
+    pub fn open(title: &Path) -> JwatchResult<Option<Self>> {
+        let Some((kind, root)) = [DiscKind::Bluray, DiscKind::Dvd]
+            .into_iter()
+            .find_map(|kind| {
+                let name = match kind {
+                    DiscKind::Bluray => "BDMV",
+                    DiscKind::Dvd => "VIDEO_TS",
+                };
+                let root = find_child(title, name)?;
+                title_dir(&root).is_some().then_some((kind, root))
+            })
+        else {
+            return Ok(None);
+        };
+
+        let mut size = 0;
+        let mut mtime = 0;
+        for entry in WalkDir::new(&root) {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                size += metadata.len();
+                let modified = metadata
+                    .modified()?
+                    .duration_since(SystemTime::UNIX_EPOCH)?;
+                mtime = mtime.max(modified.as_secs() as i64);
+            }
+        }
+
+        let disc = match kind {
+            DiscKind::Bluray => Self::bluray(&root, size, mtime)?,
+            DiscKind::Dvd => Self::dvd(&root, size, mtime)?,
+        };
+        Ok(Some(disc))
+    }
+
+    /// Main feature: the longest playlist, otherwise the largest stream
+    fn bluray(root: &Path, size: u64, mtime: i64) -> JwatchResult<Self> {
+        let stream = find_child(root, "STREAM").context("BDMV without STREAM")?;
+        // Clip name (`00800`) -> size and path
+        let mut clips = BTreeMap::new();
+        for entry in fs::read_dir(&stream)? {
+            let path = entry?.path();
+            if let Some(stem) = path.file_stem() {
+                let size = fs::metadata(&path)?.len();
+                clips.insert(stem.to_string_lossy().to_string(), (size, path));
+            }
+        }
+
+        let playlists = find_child(root, "PLAYLIST")
+            .and_then(|dir| fs::read_dir(dir).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter_map(|p| Some((parse_mpls(&fs::read(&p).ok()?)?, p)));
+        let main = playlists
+            .map(|((duration, items), path)| {
+                let size = items
+                    .iter()
+                    .filter_map(|clip| clips.get(clip))
+                    .map(|(size, _)| size)
+                    .sum::<u64>();
+                (duration, size, path)
+            })
+            .max_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+        if let Some((duration, main_size, playlist)) = main {
+            return Ok(Self {
+                kind: DiscKind::Bluray,
+                size,
+                mtime,
+                main_feature: file_name(&playlist),
+                main_feature_size: main_size,
+                duration: Some(duration),
+                probe_file: playlist,
+                ifo: None,
+            });
+        }
+
+        let (clip_size, probe_file) = clips
+            .into_values()
+            .max_by_key(|(size, _)| *size)
+            .context("empty BDMV/STREAM")?;
+        Ok(Self {
+            kind: DiscKind::Bluray,
+            size,
+            mtime,
+            main_feature: file_name(&probe_file),
+            main_feature_size: clip_size,
+            duration: None,
+            probe_file,
+            ifo: None,
+        })
+    }
+
+    /// Main feature: the title set with the most VOB data, `VTS_nn_0.VOB` being its menu
+    fn dvd(root: &Path, size: u64, mtime: i64) -> JwatchResult<Self> {
+        let mut title_sets: BTreeMap<String, (u64, BTreeSet<PathBuf>)> = BTreeMap::new();
+        for entry in fs::read_dir(root)? {
+            let path = entry?.path();
+            let name = file_name(&path).to_ascii_uppercase();
+            let Some((set, part)) = name
+                .strip_suffix(".VOB")
+                .and_then(|stem| stem.rsplit_once('_'))
+            else {
+                continue;
+            };
+            if !set.starts_with("VTS_") || part == "0" {
+                continue;
+            }
+            let title_set = title_sets.entry(set.to_owned()).or_default();
+            title_set.0 += fs::metadata(&path)?.len();
+            title_set.1.insert(path);
+        }
+        let (set, (main_size, vobs)) = title_sets
+            .into_iter()
+            .max_by_key(|(_, (size, _))| *size)
+            .context("VIDEO_TS without title VOBs")?;
+        Ok(Self {
+            kind: DiscKind::Dvd,
+            size,
+            mtime,
+            ifo: find_child(root, &format!("{set}_0.IFO")),
+            main_feature: set,
+            main_feature_size: main_size,
+            duration: None,
+            probe_file: vobs.into_iter().next().context("title set without VOBs")?,
+        })
+    }
+
+    /// Probes the main feature and describes the whole title: disc size, main feature
+    /// duration and bitrate, languages from the IFO where the VOBs lack them
+    pub fn probe(&self) -> JwatchResult<MediaInfo> {
+        let metadata = fs::metadata(&self.probe_file)?;
+        let mut info = probe_mediainfo(&self.probe_file, &metadata)
+            .wrap_err_with(|| format!("probing {} main feature", self.kind))?;
+        // A single VOB or clip is only part of the feature, scale up by size
+        let duration = self.duration.unwrap_or_else(|| {
+            info.duration
+                .mul_f64(self.main_feature_size as f64 / metadata.len().max(1) as f64)
+        });
+        info.bitrate =
+            (self.main_feature_size as f64 * 8.0 / duration.as_secs_f64().max(1.0)) as usize;
+        info.duration = duration;
+        info.size = self.size as usize;
+        info.mtime = self.mtime;
+        if let Some(ifo) = self.ifo.as_ref().and_then(|ifo| fs::read(ifo).ok()) {
+            let (audio, subtitles) = ifo_languages(&ifo);
+            for (track, lang) in info.audio_language.iter_mut().zip(audio) {
+                if let Some(lang) = lang.filter(|_| language::is_unknown(&track.language)) {
+                    track.language = lang;
+                }
+            }
+            for (track, lang) in info.subtitle_languages.iter_mut().zip(subtitles) {
+                if let Some(lang) = lang.filter(|_| language::is_unknown(&track.language)) {
+                    track.language = lang;
+                }
+            }
+        }
+        info.disc = Some(DiscInfo {
+            kind: self.kind,
+            main_feature: self.main_feature.clone(),
+            main_feature_size: self.main_feature_size,
+        });
+        Ok(info)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Duration and clip names of a Blu-ray playlist
+fn parse_mpls(data: &[u8]) -> Option<(Duration, Vec<String>)> {
+    let u16_at = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+    if data.get(..4)? != b"MPLS" {
+        return None;
+    }
+    let playlist = u32_at(8)? as usize;
+    let items = u16_at(playlist + 6)?;
+
+    let mut ticks = 0u64;
+    let mut clips = vec![];
+    let mut item = playlist + 10;
+    for _ in 0..items {
+        let clip = String::from_utf8_lossy(data.get(item + 2..item + 7)?).to_string();
+        let in_time = u32_at(item + 14)?;
+        let out_time = u32_at(item + 18)?;
+        ticks += u64::from(out_time.saturating_sub(in_time));
+        if !clips.contains(&clip) {
+            clips.push(clip);
+        }
+        item += 2 + u16_at(item)? as usize;
+    }
+    Some((
+        Duration::from_secs_f64(ticks as f64 / MPLS_TICKS_PER_SEC),
+        clips,
+    ))
+}
+
+/// Audio and subpicture stream languages from the VTSI_MAT of a `VTS_nn_0.IFO`
+fn ifo_languages(ifo: &[u8]) -> (Vec<Option<String>>, Vec<Option<String>>) {
+    let streams = |count_at: usize, attrs_at: usize, attr_len: usize| {
+        let count = ifo
+            .get(count_at..count_at + 2)
+            .map_or(0, |c| u16::from_be_bytes([c[0], c[1]]) as usize);
+        (0..count)
+            .map(|i| {
+                let code = ifo.get(attrs_at + i * attr_len + 2..attrs_at + i * attr_len + 4)?;
+                code.iter()
+                    .all(u8::is_ascii_lowercase)
+                    .then(|| language::normalize(&String::from_utf8_lossy(code)))
+            })
+            .collect()
+    };
+    (
+        streams(IFO_AUDIO_COUNT, IFO_AUDIO_ATTRS, 8),
+        streams(IFO_SUBPICTURE_COUNT, IFO_SUBPICTURE_ATTRS, 6),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PlayItem of the clip `clip`.m2ts, playing from `in_time` to `out_time`
+    fn play_item(clip: &str, in_time: u32, out_time: u32) -> Vec<u8> {
+        let mut item = 20u16.to_be_bytes().to_vec();
+        item.extend_from_slice(clip.as_bytes());
+        item.extend_from_slice(b"M2TS");
+        item.extend_from_slice(&[0; 3]);
+        item.extend_from_slice(&in_time.to_be_bytes());
+        item.extend_from_slice(&out_time.to_be_bytes());
+        item
+    }
+
+    fn mpls(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"MPLS0200".to_vec();
+        data.extend_from_slice(&16u32.to_be_bytes());
+        data.extend_from_slice(&[0; 4]);
+        // PlayList: length, reserved, item count, subpath count
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&(items.len() as u16).to_be_bytes());
+        data.extend_from_slice(&[0; 2]);
+        for item in items {
+            data.extend_from_slice(item);
+        }
+        data
+    }
+
+    #[test]
+    fn mpls_sums_items_and_lists_each_clip_once() {
+        let minute = 60 * MPLS_TICKS_PER_SEC as u32;
+        let data = mpls(&[
+            play_item("00001", 1000, 1000 + minute),
+            play_item("00002", 0, 2 * minute),
+            play_item("00001", 0, minute),
+        ]);
+        let (duration, clips) = parse_mpls(&data).unwrap();
+        assert_eq!(duration, Duration::from_secs(4 * 60));
+        assert_eq!(clips, ["00001", "00002"]);
+    }
+
+    #[test]
+    fn mpls_rejects_other_and_truncated_files() {
+        let mut other = mpls(&[play_item("00001", 0, 45_000)]);
+        other[..4].copy_from_slice(b"HDMV");
+        assert_eq!(parse_mpls(&other), None);
+
+        let data = mpls(&[play_item("00001", 0, 45_000)]);
+        assert_eq!(parse_mpls(&data[..data.len() - 1]), None);
+        assert_eq!(parse_mpls(b"MPL"), None);
+    }
+
+    #[test]
+    fn ifo_languages_per_stream() {
+        let mut ifo = vec![0; 0x300];
+        ifo[IFO_AUDIO_COUNT..IFO_AUDIO_COUNT + 2].copy_from_slice(&2u16.to_be_bytes());
+        ifo[IFO_AUDIO_ATTRS + 2..IFO_AUDIO_ATTRS + 4].copy_from_slice(b"en");
+        // The second audio stream has no language
+        ifo[IFO_SUBPICTURE_COUNT..IFO_SUBPICTURE_COUNT + 2].copy_from_slice(&2u16.to_be_bytes());
+        ifo[IFO_SUBPICTURE_ATTRS + 2..IFO_SUBPICTURE_ATTRS + 4].copy_from_slice(b"de");
+        ifo[IFO_SUBPICTURE_ATTRS + 8..IFO_SUBPICTURE_ATTRS + 10].copy_from_slice(b"iw");
+
+        let (audio, subtitles) = ifo_languages(&ifo);
+        assert_eq!(audio, [Some("en".to_owned()), None]);
+        assert_eq!(subtitles, [Some("de".to_owned()), Some("he".to_owned())]);
+    }
+
+    #[test]
+    fn ifo_languages_of_a_truncated_file() {
+        let mut ifo = vec![0; IFO_AUDIO_ATTRS + 4];
+        ifo[IFO_AUDIO_COUNT..IFO_AUDIO_COUNT + 2].copy_from_slice(&3u16.to_be_bytes());
+        ifo[IFO_AUDIO_ATTRS + 2..IFO_AUDIO_ATTRS + 4].copy_from_slice(b"fr");
+
+        let (audio, subtitles) = ifo_languages(&ifo);
+        assert_eq!(audio, [Some("fr".to_owned()), None, None]);
+        assert!(subtitles.is_empty());
+    }
+}
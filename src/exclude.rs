@@ -0,0 +1,68 @@
+use crate::JwatchResult;
+use color_eyre::eyre::Context;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Per-directory ignore file, gitignore syntax, applying to the directory and everything below
+pub const IGNORE_FILE_NAME: &str = ".jwatchignore";
+
+/// `--exclude` globs, matched like a `.jwatchignore` in the scanned folder
+pub fn build_excludes(root: &Path, globs: &[String]) -> JwatchResult<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for glob in globs {
+        builder
+            .add_line(None, glob)
+            .with_context(|| format!("invalid --exclude {glob:?}"))?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Ignore files of the directories above a walked one. Cheap to clone, every
+/// subdirectory carries its own.
+#[derive(Clone, Default)]
+pub struct Ignores {
+    /// Innermost directory last
+    stack: Vec<Arc<Gitignore>>,
+}
+
+impl Ignores {
+    /// From [`Self::dirs`], re-reading the ignore files that still exist
+    pub fn from_dirs(dirs: &[PathBuf]) -> JwatchResult<Self> {
+        let mut ignores = Self::default();
+        for dir in dirs.iter().filter(|d| d.join(IGNORE_FILE_NAME).is_file()) {
+            ignores.push(dir)?;
+        }
+        Ok(ignores)
+    }
+
+    /// The directories whose ignore files apply, outermost first
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        self.stack.iter().map(|i| i.path().to_path_buf()).collect()
+    }
+
+    /// Adds the ignore file of `dir`, applying to everything below it
+    pub fn push(&mut self, dir: &Path) -> JwatchResult<()> {
+        let file = dir.join(IGNORE_FILE_NAME);
+        let mut builder = GitignoreBuilder::new(dir);
+        if let Some(e) = builder.add(&file) {
+            return Err(e).with_context(|| format!("invalid {}", file.display()));
+        }
+        self.stack.push(Arc::new(builder.build()?));
+        Ok(())
+    }
+
+    /// Inner ignore files beat outer ones
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for ignore in self.stack.iter().rev() {
+            let matched = ignore.matched(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                break;
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,386 @@
+use crate::JwatchResult;
+use crate::language;
+use crate::metastructs::LangTrack;
+use color_eyre::eyre::WrapErr;
+use std::path::Path;
+use std::process::Command;
+
+/// Only this much of an embedded track is extracted, ffmpeg has to read the interleaved video too
+const EXTRACT_SECONDS: &str = "900";
+/// Below this many recognized words a guess is not trusted
+const MIN_HITS: usize = 20;
+/// The best language needs this many times the hits of the runner-up
+const MIN_MARGIN: f64 = 1.5;
+/// Share of letters in a non-Latin script that decides the language on its own
+const MIN_SCRIPT_SHARE: f64 = 0.5;
+
+/// Frequent short words per language, for Latin-script subtitles
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "to", "of", "you", "it", "is", "that", "in", "what", "me", "this", "we",
+            "he", "for", "my", "on", "your", "have", "be", "not", "with", "was", "are", "do",
+            "know", "just", "but", "get", "they",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "und", "das", "ist", "ich", "nicht", "du", "zu", "es", "sie", "ein",
+            "wir", "mit", "den", "auf", "was", "mir", "ja", "hast", "dich", "sich", "eine", "von",
+            "wie", "dem", "mich", "noch", "auch", "aber",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "je", "vous", "est", "pas", "un", "une", "que", "tu", "il",
+            "des", "ce", "ne", "qui", "pour", "on", "nous", "mais", "elle", "du", "dans", "ça",
+            "suis", "moi", "oui", "bien", "avec",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "que", "no", "los", "se", "lo", "por", "qué", "una", "te", "las", "con", "para",
+            "del", "está", "pero", "eso", "sí", "muy", "bien", "esto", "como", "estoy", "tengo",
+            "usted", "yo", "ella", "aquí", "nada", "todo",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "di", "che", "non", "il", "è", "per", "mi", "ti", "ho", "si", "ma", "sono", "cosa",
+            "della", "questo", "mio", "ci", "sei", "hai", "come", "bene", "gli", "lei", "qui",
+            "perché", "io", "anche", "tutto", "niente", "solo",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "não", "o", "é", "um", "para", "eu", "do", "da", "em", "você", "uma", "os", "no", "na",
+            "isso", "mas", "está", "ele", "ela", "muito", "bem", "aqui", "tudo", "meu", "sim",
+            "ao", "pra", "tem", "estou", "vai",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "ik", "je", "van", "is", "dat", "niet", "ze", "wat", "hij",
+            "op", "te", "zijn", "we", "er", "maar", "met", "voor", "die", "dit", "heb", "jij",
+            "wel", "hier", "nog", "zo", "ook",
+        ],
+    ),
+    (
+        "sv",
+        &[
+            "och", "att", "det", "är", "jag", "som", "på", "du", "inte", "har", "vi", "för", "med",
+            "han", "den", "till", "var", "så", "vad", "kan", "mig", "dig", "hon", "här", "nu",
+            "ska", "också", "eller", "bara", "om",
+        ],
+    ),
+    (
+        "da",
+        &[
+            "og", "det", "er", "jeg", "at", "en", "til", "du", "ikke", "på", "har", "som", "vi",
+            "med", "han", "for", "den", "var", "hvad", "mig", "dig", "nu", "også", "hun", "her",
+            "skal", "kan", "bare", "noget", "hvor",
+        ],
+    ),
+    (
+        "no",
+        &[
+            "og", "det", "er", "jeg", "at", "en", "til", "du", "ikke", "på", "har", "som", "vi",
+            "med", "han", "for", "den", "var", "hva", "meg", "deg", "nå", "også", "hun", "her",
+            "skal", "kan", "bare", "noe", "hvor",
+        ],
+    ),
+    (
+        "fi",
+        &[
+            "ja", "on", "ei", "se", "että", "en", "mitä", "minä", "sinä", "hän", "me", "tämä",
+            "mutta", "kun", "oli", "olen", "olet", "niin", "sen", "minun", "sinun", "mikä", "kuin",
+            "jos", "nyt", "vain", "tässä", "joo", "no", "mä",
+        ],
+    ),
+    (
+        "pl",
+        &[
+            "nie", "to", "się", "na", "że", "jest", "do", "co", "jak", "tak", "ja", "ty", "mnie",
+            "mi", "już", "za", "ale", "tym", "czy", "jestem", "był", "tylko", "może", "wiem", "tu",
+            "go", "mam", "dobrze", "cię", "jesteś",
+        ],
+    ),
+    (
+        "cs",
+        &[
+            "to", "je", "se", "na", "že", "ne", "jsem", "co", "jak", "tak", "ale", "mi", "jsi",
+            "už", "by", "do", "tady", "jsme", "tě", "mě", "být", "není", "ano", "jen", "víš",
+            "proč", "když", "ty", "ten", "bylo",
+        ],
+    ),
+    (
+        "tr",
+        &[
+            "bir", "ve", "bu", "da", "ne", "için", "ben", "sen", "çok", "değil", "var", "mı",
+            "ama", "gibi", "bana", "seni", "beni", "şey", "evet", "hayır", "mi", "o", "daha",
+            "burada", "neden", "nasıl", "iyi", "sana", "onu", "şimdi",
+        ],
+    ),
+];
+
+/// Guesses the language of subtitle text, `None` unless the guess is confident
+pub fn detect(text: &str) -> Option<&'static str> {
+    if let Some(lang) = detect_script(text) {
+        return Some(lang);
+    }
+
+    let words = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let mut scores = STOPWORDS
+        .iter()
+        .map(|(lang, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count();
+            (*lang, hits)
+        })
+        .collect::<Vec<_>>();
+    scores.sort_by_key(|&(_, hits)| std::cmp::Reverse(hits));
+    let (best, hits) = scores[0];
+    let runner_up = scores[1].1;
+    (hits >= MIN_HITS && hits as f64 >= runner_up as f64 * MIN_MARGIN).then_some(best)
+}
+
+/// Languages recognizable by their script alone
+fn detect_script(text: &str) -> Option<&'static str> {
+    let mut letters = 0usize;
+    let mut counts: Vec<(&'static str, usize)> = vec![];
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        let lang = match c as u32 {
+            0x3040..=0x30FF => "ja", // Hiragana, Katakana
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => "ko",
+            0x4E00..=0x9FFF => "zh", // Also Japanese Kanji, kana decides then
+            0x0400..=0x04FF => match c {
+                'і' | 'ї' | 'є' | 'ґ' | 'І' | 'Ї' | 'Є' | 'Ґ' => "uk",
+                _ => "ru",
+            },
+            0x0600..=0x06FF => "ar",
+            0x0590..=0x05FF => "he",
+            0x0370..=0x03FF => "el",
+            0x0E00..=0x0E7F => "th",
+            _ => continue,
+        };
+        match counts.iter_mut().find(|(l, _)| *l == lang) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((lang, 1)),
+        }
+    }
+    let share = |lang| {
+        counts
+            .iter()
+            .find(|(l, _)| *l == lang)
+            .map_or(0, |(_, n)| *n) as f64
+            / letters as f64
+    };
+    // Kana anywhere makes Han characters Japanese
+    if share("ja") > 0.05 && share("ja") + share("zh") > MIN_SCRIPT_SHARE {
+        return Some("ja");
+    }
+    // Ukrainian shares most of its letters with Russian
+    if share("uk") > 0.01 && share("uk") + share("ru") > MIN_SCRIPT_SHARE {
+        return Some("uk");
+    }
+    counts
+        .into_iter()
+        .find(|(_, n)| *n as f64 / letters as f64 > MIN_SCRIPT_SHARE)
+        .map(|(lang, _)| lang)
+}
+
+/// The spoken text of an SRT, ASS/SSA or WebVTT file: no counters, timings, styles or markup
+pub fn subtitle_text(raw: &str) -> String {
+    let ass = raw.contains("[Script Info]");
+    let mut text = String::new();
+    // In a WebVTT header, note, style or region block, which run up to the next blank line
+    let mut in_block = false;
+    let mut lines = raw.lines().map(str::trim).peekable();
+    while let Some(line) = lines.next() {
+        if line.is_empty() {
+            in_block = false;
+            continue;
+        }
+        // Cue identifiers and SRT counters
+        if lines.peek().is_some_and(|next| next.contains("-->")) {
+            continue;
+        }
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        if in_block || matches!(keyword, "WEBVTT" | "NOTE" | "STYLE" | "REGION") {
+            in_block = true;
+            continue;
+        }
+        let line = if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+            // ASS: the text is the 10th field and may itself contain commas
+            match dialogue.splitn(10, ',').nth(9) {
+                Some(t) => t,
+                None => continue,
+            }
+        } else if line.contains("-->") || line.starts_with('[') {
+            continue;
+        } else if ass {
+            // Any other ASS line is header or style
+            continue;
+        } else {
+            line
+        };
+        text.push_str(&strip_markup(line));
+        text.push('\n');
+    }
+    text
+}
+
+/// Drops `<i>`-style tags and `{\an8}`-style ASS overrides, and turns `\N` into spaces
+fn strip_markup(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut depth = 0u32;
+    for c in line.chars() {
+        match c {
+            '<' | '{' => depth += 1,
+            '>' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("\\N", " ").replace("\\n", " ")
+}
+
+/// Extracts stream `stream` (counting all streams of the file) as SRT and guesses its
+/// language. Only failing to run ffmpeg at all is an error.
+pub fn detect_embedded(file: &Path, stream: u32) -> JwatchResult<Option<&'static str>> {
+    let cmd = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostdin", "-t", EXTRACT_SECONDS, "-i"])
+        .arg(file)
+        .args(["-map", &format!("0:{stream}"), "-f", "srt", "-"])
+        .output()
+        .wrap_err("failed to run ffmpeg for subtitle language detection")?;
+    if !cmd.status.success() {
+        // Most likely image-based subtitles, which cannot be converted to text
+        return Ok(None);
+    }
+    Ok(detect(&subtitle_text(&String::from_utf8_lossy(
+        &cmd.stdout,
+    ))))
+}
+
+/// Guesses the language of every untagged subtitle track not looked at yet.
+/// Returns whether any track changed.
+pub fn detect_untagged(file: &Path, subtitles: &mut [LangTrack]) -> JwatchResult<bool> {
+    let mut changed = false;
+    for track in subtitles.iter_mut() {
+        if !track.needs_detection() {
+            continue;
+        }
+        // Embedded tracks that need detection always have their own stream
+        let Some(stream) = track.stream else {
+            continue;
+        };
+        let guess = detect_embedded(file, stream)?.unwrap_or(language::UNDETERMINED);
+        track.detected = Some(guess.to_owned());
+        changed = true;
+    }
+    Ok(changed)
+}
+
+/// `mkvpropedit` invocations tagging the confidently guessed subtitle languages of a Matroska file
+pub fn tagging_commands(file: &Path, subtitles: &[LangTrack]) -> Vec<String> {
+    let is_matroska = file
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mkv"));
+    if !is_matroska {
+        return vec![];
+    }
+    // POSIX shell single quotes, with embedded ones closed, escaped and reopened
+    let quoted = format!("'{}'", file.to_string_lossy().replace('\'', r"'\''"));
+    subtitles
+        .iter()
+        .filter(|t| language::is_unknown(&t.language))
+        .filter_map(|t| Some((t.stream?, language::bibliographic(t.detected.as_deref()?)?)))
+        .map(|(stream, lang)| {
+            // mkvpropedit numbers all tracks from 1, in stream order
+            format!(
+                "mkvpropedit {quoted} --edit track:{} --set language={lang}",
+                stream + 1
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_text_without_counters_timings_and_markup() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Where are you going?</i>\r\n\r\n\
+                   2\r\n00:00:03,000 --> 00:00:04,000\r\n{\\an8}<font color=\"#ffff00\">Home.</font>\r\n";
+        assert_eq!(subtitle_text(srt), "Where are you going?\nHome.\n");
+    }
+
+    #[test]
+    fn ass_dialogue_text_only() {
+        let ass = "[Script Info]\nTitle: Example\nScriptType: v4.00+\n\n\
+                   [V4+ Styles]\nFormat: Name, Fontname, Fontsize\nStyle: Default,Arial,20\n\n\
+                   [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Well, well, {\\i1}well\\Nif it isn't you\n\
+                   Comment: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Not spoken\n";
+        assert_eq!(subtitle_text(ass), "Well, well, well if it isn't you\n");
+    }
+
+    #[test]
+    fn webvtt_text_without_headers_and_blocks() {
+        let vtt = "WEBVTT - Some title\nKind: captions\nLanguage: en\n\n\
+                   NOTE\nThis is a comment\nover two lines\n\n\
+                   STYLE\n::cue { color: yellow }\n\n\
+                   NOTE Before a cue without identifier\n\n\
+                   00:00.500 --> 00:01.000\nFirst\n\n\
+                   intro\n00:01.000 --> 00:04.000 align:start position:10%\n<v Roger>Hello there\n\n\
+                   00:05.000 --> 00:06.000\n<c.loud>Quiet!</c>\n";
+        assert_eq!(subtitle_text(vtt), "First\nHello there\nQuiet!\n");
+    }
+
+    #[test]
+    fn detect_confident_stopwords() {
+        let english =
+            "What do you know about this? I have to get the money to them, and that is it.\n";
+        assert_eq!(detect(&english.repeat(3)), Some("en"));
+        let german =
+            "Ich weiß nicht, was du mit dem Auto machst. Aber das ist auch nicht mein Problem.\n";
+        assert_eq!(detect(&german.repeat(3)), Some("de"));
+    }
+
+    #[test]
+    fn detect_rejects_ambiguous_or_scarce_text() {
+        assert_eq!(detect("What is this?"), None);
+        assert_eq!(detect(""), None);
+        // Danish and Norwegian share these words
+        let nordic = "Det er jeg ikke, og du har ikke set den. Han er her nu, og vi kan med det.\n";
+        assert_eq!(detect(&nordic.repeat(3)), None);
+    }
+
+    #[test]
+    fn detect_by_script() {
+        assert_eq!(detect("お元気ですか？私は元気です。"), Some("ja"));
+        assert_eq!(detect("你好吗？我很好，谢谢。"), Some("zh"));
+        assert_eq!(detect("Где ты был всю ночь? Я тебя ждал."), Some("ru"));
+        assert_eq!(
+            detect("Привіт, як справи? Дякую, все добре, їжте."),
+            Some("uk")
+        );
+        assert_eq!(detect("안녕하세요, 잘 지내세요?"), Some("ko"));
+    }
+}
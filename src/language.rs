@@ -0,0 +1,306 @@
+/// ISO 639-1, ISO 639-2/T (= ISO 639-3 for these), ISO 639-2/B if it differs, English name
+const LANGUAGES: &[(&str, &str, Option<&str>, &str)] = &[
+    ("aa", "aar", None, "Afar"),
+    ("ab", "abk", None, "Abkhazian"),
+    ("ae", "ave", None, "Avestan"),
+    ("af", "afr", None, "Afrikaans"),
+    ("ak", "aka", None, "Akan"),
+    ("am", "amh", None, "Amharic"),
+    ("an", "arg", None, "Aragonese"),
+    ("ar", "ara", None, "Arabic"),
+    ("as", "asm", None, "Assamese"),
+    ("av", "ava", None, "Avaric"),
+    ("ay", "aym", None, "Aymara"),
+    ("az", "aze", None, "Azerbaijani"),
+    ("ba", "bak", None, "Bashkir"),
+    ("be", "bel", None, "Belarusian"),
+    ("bg", "bul", None, "Bulgarian"),
+    ("bi", "bis", None, "Bislama"),
+    ("bm", "bam", None, "Bambara"),
+    ("bn", "ben", None, "Bengali"),
+    ("bo", "bod", Some("tib"), "Tibetan"),
+    ("br", "bre", None, "Breton"),
+    ("bs", "bos", None, "Bosnian"),
+    ("ca", "cat", None, "Catalan"),
+    ("ce", "che", None, "Chechen"),
+    ("ch", "cha", None, "Chamorro"),
+    ("co", "cos", None, "Corsican"),
+    ("cr", "cre", None, "Cree"),
+    ("cs", "ces", Some("cze"), "Czech"),
+    ("cu", "chu", None, "Church Slavic"),
+    ("cv", "chv", None, "Chuvash"),
+    ("cy", "cym", Some("wel"), "Welsh"),
+    ("da", "dan", None, "Danish"),
+    ("de", "deu", Some("ger"), "German"),
+    ("dv", "div", None, "Divehi"),
+    ("dz", "dzo", None, "Dzongkha"),
+    ("ee", "ewe", None, "Ewe"),
+    ("el", "ell", Some("gre"), "Greek"),
+    ("en", "eng", None, "English"),
+    ("eo", "epo", None, "Esperanto"),
+    ("es", "spa", None, "Spanish"),
+    ("et", "est", None, "Estonian"),
+    ("eu", "eus", Some("baq"), "Basque"),
+    ("fa", "fas", Some("per"), "Persian"),
+    ("ff", "ful", None, "Fulah"),
+    ("fi", "fin", None, "Finnish"),
+    ("fj", "fij", None, "Fijian"),
+    ("fo", "fao", None, "Faroese"),
+    ("fr", "fra", Some("fre"), "French"),
+    ("fy", "fry", None, "Western Frisian"),
+    ("ga", "gle", None, "Irish"),
+    ("gd", "gla", None, "Gaelic"),
+    ("gl", "glg", None, "Galician"),
+    ("gn", "grn", None, "Guarani"),
+    ("gu", "guj", None, "Gujarati"),
+    ("gv", "glv", None, "Manx"),
+    ("ha", "hau", None, "Hausa"),
+    ("he", "heb", None, "Hebrew"),
+    ("hi", "hin", None, "Hindi"),
+    ("ho", "hmo", None, "Hiri Motu"),
+    ("hr", "hrv", None, "Croatian"),
+    ("ht", "hat", None, "Haitian"),
+    ("hu", "hun", None, "Hungarian"),
+    ("hy", "hye", Some("arm"), "Armenian"),
+    ("hz", "her", None, "Herero"),
+    ("ia", "ina", None, "Interlingua"),
+    ("id", "ind", None, "Indonesian"),
+    ("ie", "ile", None, "Interlingue"),
+    ("ig", "ibo", None, "Igbo"),
+    ("ii", "iii", None, "Sichuan Yi"),
+    ("ik", "ipk", None, "Inupiaq"),
+    ("io", "ido", None, "Ido"),
+    ("is", "isl", Some("ice"), "Icelandic"),
+    ("it", "ita", None, "Italian"),
+    ("iu", "iku", None, "Inuktitut"),
+    ("ja", "jpn", None, "Japanese"),
+    ("jv", "jav", None, "Javanese"),
+    ("ka", "kat", Some("geo"), "Georgian"),
+    ("kg", "kon", None, "Kongo"),
+    ("ki", "kik", None, "Kikuyu"),
+    ("kj", "kua", None, "Kuanyama"),
+    ("kk", "kaz", None, "Kazakh"),
+    ("kl", "kal", None, "Kalaallisut"),
+    ("km", "khm", None, "Khmer"),
+    ("kn", "kan", None, "Kannada"),
+    ("ko", "kor", None, "Korean"),
+    ("kr", "kau", None, "Kanuri"),
+    ("ks", "kas", None, "Kashmiri"),
+    ("ku", "kur", None, "Kurdish"),
+    ("kv", "kom", None, "Komi"),
+    ("kw", "cor", None, "Cornish"),
+    ("ky", "kir", None, "Kirghiz"),
+    ("la", "lat", None, "Latin"),
+    ("lb", "ltz", None, "Luxembourgish"),
+    ("lg", "lug", None, "Ganda"),
+    ("li", "lim", None, "Limburgan"),
+    ("ln", "lin", None, "Lingala"),
+    ("lo", "lao", None, "Lao"),
+    ("lt", "lit", None, "Lithuanian"),
+    ("lu", "lub", None, "Luba-Katanga"),
+    ("lv", "lav", None, "Latvian"),
+    ("mg", "mlg", None, "Malagasy"),
+    ("mh", "mah", None, "Marshallese"),
+    ("mi", "mri", Some("mao"), "Maori"),
+    ("mk", "mkd", Some("mac"), "Macedonian"),
+    ("ml", "mal", None, "Malayalam"),
+    ("mn", "mon", None, "Mongolian"),
+    ("mr", "mar", None, "Marathi"),
+    ("ms", "msa", Some("may"), "Malay"),
+    ("mt", "mlt", None, "Maltese"),
+    ("my", "mya", Some("bur"), "Burmese"),
+    ("na", "nau", None, "Nauru"),
+    ("nb", "nob", None, "Norwegian Bokmal"),
+    ("nd", "nde", None, "North Ndebele"),
+    ("ne", "nep", None, "Nepali"),
+    ("ng", "ndo", None, "Ndonga"),
+    ("nl", "nld", Some("dut"), "Dutch"),
+    ("nn", "nno", None, "Norwegian Nynorsk"),
+    ("no", "nor", None, "Norwegian"),
+    ("nr", "nbl", None, "South Ndebele"),
+    ("nv", "nav", None, "Navajo"),
+    ("ny", "nya", None, "Chichewa"),
+    ("oc", "oci", None, "Occitan"),
+    ("oj", "oji", None, "Ojibwa"),
+    ("om", "orm", None, "Oromo"),
+    ("or", "ori", None, "Oriya"),
+    ("os", "oss", None, "Ossetian"),
+    ("pa", "pan", None, "Punjabi"),
+    ("pi", "pli", None, "Pali"),
+    ("pl", "pol", None, "Polish"),
+    ("ps", "pus", None, "Pashto"),
+    ("pt", "por", None, "Portuguese"),
+    ("qu", "que", None, "Quechua"),
+    ("rm", "roh", None, "Romansh"),
+    ("rn", "run", None, "Rundi"),
+    ("ro", "ron", Some("rum"), "Romanian"),
+    ("ru", "rus", None, "Russian"),
+    ("rw", "kin", None, "Kinyarwanda"),
+    ("sa", "san", None, "Sanskrit"),
+    ("sc", "srd", None, "Sardinian"),
+    ("sd", "snd", None, "Sindhi"),
+    ("se", "sme", None, "Northern Sami"),
+    ("sg", "sag", None, "Sango"),
+    ("si", "sin", None, "Sinhala"),
+    ("sk", "slk", Some("slo"), "Slovak"),
+    ("sl", "slv", None, "Slovenian"),
+    ("sm", "smo", None, "Samoan"),
+    ("sn", "sna", None, "Shona"),
+    ("so", "som", None, "Somali"),
+    ("sq", "sqi", Some("alb"), "Albanian"),
+    ("sr", "srp", None, "Serbian"),
+    ("ss", "ssw", None, "Swati"),
+    ("st", "sot", None, "Southern Sotho"),
+    ("su", "sun", None, "Sundanese"),
+    ("sv", "swe", None, "Swedish"),
+    ("sw", "swa", None, "Swahili"),
+    ("ta", "tam", None, "Tamil"),
+    ("te", "tel", None, "Telugu"),
+    ("tg", "tgk", None, "Tajik"),
+    ("th", "tha", None, "Thai"),
+    ("ti", "tir", None, "Tigrinya"),
+    ("tk", "tuk", None, "Turkmen"),
+    ("tl", "tgl", None, "Tagalog"),
+    ("tn", "tsn", None, "Tswana"),
+    ("to", "ton", None, "Tonga"),
+    ("tr", "tur", None, "Turkish"),
+    ("ts", "tso", None, "Tsonga"),
+    ("tt", "tat", None, "Tatar"),
+    ("tw", "twi", None, "Twi"),
+    ("ty", "tah", None, "Tahitian"),
+    ("ug", "uig", None, "Uyghur"),
+    ("uk", "ukr", None, "Ukrainian"),
+    ("ur", "urd", None, "Urdu"),
+    ("uz", "uzb", None, "Uzbek"),
+    ("ve", "ven", None, "Venda"),
+    ("vi", "vie", None, "Vietnamese"),
+    ("vo", "vol", None, "Volapuk"),
+    ("wa", "wln", None, "Walloon"),
+    ("wo", "wol", None, "Wolof"),
+    ("xh", "xho", None, "Xhosa"),
+    ("yi", "yid", None, "Yiddish"),
+    ("yo", "yor", None, "Yoruba"),
+    ("za", "zha", None, "Zhuang"),
+    ("zh", "zho", Some("chi"), "Chinese"),
+    ("zu", "zul", None, "Zulu"),
+];
+
+/// Codes outside [`LANGUAGES`] that still mean one of its languages:
+/// deprecated ISO 639-1 codes and ISO 639-3 individual languages mediainfo commonly reports
+const ALIASES: &[(&str, &str)] = &[
+    ("iw", "he"),
+    ("in", "id"),
+    ("ji", "yi"),
+    ("mo", "ro"),
+    ("cmn", "zh"),
+    ("arb", "ar"),
+    ("pes", "fa"),
+    ("zsm", "ms"),
+    ("ekk", "et"),
+    ("lvs", "lv"),
+];
+
+/// ISO 639 codes that are valid but name no particular language
+const SPECIAL: &[&str] = &["und", "mis", "mul", "zxx"];
+
+/// Canonical form of a known language: the ISO 639-1 code where one exists.
+/// Accepts ISO 639-1/2B/2T/3 codes, BCP-47 tags (region and script subtags are dropped,
+/// so `en-US` and `zh-Hans` become `en` and `zh`) and English names, all case-insensitive.
+pub fn canonical(tag: &str) -> Option<&'static str> {
+    let tag = tag.trim();
+    if let Some((a2, _, _, _)) = LANGUAGES
+        .iter()
+        .find(|(_, _, _, name)| name.eq_ignore_ascii_case(tag))
+    {
+        return Some(a2);
+    }
+
+    let primary = tag
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if let Some(&(_, canonical)) = ALIASES.iter().find(|(alias, _)| *alias == primary) {
+        return Some(canonical);
+    }
+    if let Some(&special) = SPECIAL.iter().find(|s| **s == primary) {
+        return Some(special);
+    }
+    LANGUAGES
+        .iter()
+        .find(|(a2, t, b, _)| *a2 == primary || *t == primary || *b == Some(primary.as_str()))
+        .map(|(a2, _, _, _)| *a2)
+}
+
+/// Like [`canonical`], but keeps unknown tags as their lowercased primary subtag
+/// instead of rejecting them, for languages reported by mediainfo
+pub fn normalize(tag: &str) -> String {
+    match canonical(tag) {
+        Some(canonical) => canonical.to_owned(),
+        None => tag
+            .trim()
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase(),
+    }
+}
+
+/// ISO 639-2/B code of a canonical language, the form mkvtoolnix has always accepted
+pub fn bibliographic(canonical: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(a2, _, _, _)| *a2 == canonical)
+        .map(|(_, t, b, _)| b.unwrap_or(t))
+}
+
+/// Marker for tracks without a language tag
+pub const UNDETERMINED: &str = "und";
+
+/// Untagged tracks and ISO 639 codes that name no particular language
+pub fn is_unknown(lang: &str) -> bool {
+    matches!(lang, "" | "und" | "mis" | "zxx")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_accepts_every_code_form() {
+        for tag in ["de", "deu", "ger", "GER", "German", "german", " de "] {
+            assert_eq!(canonical(tag), Some("de"), "{tag}");
+        }
+        // Only languages with an ISO 639-1 code are known
+        assert_eq!(canonical("haw"), None);
+    }
+
+    #[test]
+    fn canonical_drops_bcp47_subtags() {
+        assert_eq!(canonical("en-US"), Some("en"));
+        assert_eq!(canonical("zh-Hans-CN"), Some("zh"));
+        assert_eq!(canonical("pt_BR"), Some("pt"));
+    }
+
+    #[test]
+    fn canonical_aliases_and_special_codes() {
+        assert_eq!(canonical("iw"), Some("he"));
+        assert_eq!(canonical("cmn"), Some("zh"));
+        assert_eq!(canonical("cmn-Hant"), Some("zh"));
+        assert_eq!(canonical("und"), Some("und"));
+        assert_eq!(canonical("ZXX"), Some("zxx"));
+    }
+
+    #[test]
+    fn canonical_rejects_unknown_tags() {
+        for tag in ["", "xx", "english language", "qaa", "e"] {
+            assert_eq!(canonical(tag), None, "{tag}");
+        }
+    }
+
+    #[test]
+    fn normalize_keeps_unknown_primary_subtags() {
+        assert_eq!(normalize("ger"), "de");
+        assert_eq!(normalize("QAA-x"), "qaa");
+    }
+}
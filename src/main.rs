@@ -1,34 +1,85 @@
-use crate::argparse::Args;
+use crate::argparse::{Args, Command};
+use crate::baseline::Baseline;
 use crate::cachedb::CacheDB;
+use crate::config::{
+    CONFIG_FILE_NAME, Config, InvalidLayer, LAYER_FILE_NAME, PolicyLayers, parse_rule_list,
+};
+use crate::disc::Disc;
 use crate::mediainfo::probe_mediainfo;
 use crate::metastructs::MediaInfo;
+use crate::resume::ScanState;
+use crate::rules::{Finding, Policy, Rule, Savings};
+use crate::sample::{Estimate, SampleSize};
+use crate::schedule::{Failure, Order, ProbeQueue};
+use crate::sidecar::Sidecars;
+use crate::sniff::Container;
+use crate::walk::DirListing;
 use color_eyre::Report;
-use color_eyre::eyre::{ContextCompat, bail, eyre};
-use indicatif::{HumanBytes, ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
+use color_eyre::eyre::{Context, ContextCompat, bail, eyre};
+use ignore::gitignore::Gitignore;
+use indicatif::{HumanBytes, ProgressBar, ProgressFinish, ProgressStyle};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
 use std::time::{Duration, Instant, SystemTime};
-use walkdir::{DirEntry, WalkDir};
 
 mod argparse;
+mod baseline;
 mod cachedb;
+mod config;
+mod disc;
+mod exclude;
+mod langdetect;
+mod language;
 mod mediainfo;
 mod metastructs;
+mod original_language;
+mod resume;
+mod rules;
+mod sample;
+mod schedule;
+mod sidecar;
+mod sniff;
+mod status;
+mod walk;
+mod watch;
 
 pub type JwatchResult<T> = Result<T, Report>;
 
-const VIDEO_EXTENSIONS: &[&str] = &["mkv", "mp4", "avi", "mov", "flv", "wmv", "webm", "m4v"];
-const ACCEPTED_BITRATE_RANGE: std::ops::Range<f64> = 0.2..20.0;
-const ACCEPTED_LANGS: &[&str] = &["en", "de"];
+/// Process exit codes, also listed in `--help`. Eyre errors outside these categories exit with 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ExitStatus {
+    Clean = 0,
+    /// Findings on `--fail-on` rules, with `--baseline` only those missing from it
+    Findings = 2,
+    ProbeFailures = 3,
+    CacheDb = 4,
+    Config = 5,
+    /// Conventional exit code for SIGINT
+    Interrupted = 130,
+}
+
+impl ExitStatus {
+    /// Prints the error like eyre would when returned from main, then exits with `self`
+    fn fail(self, e: Report) -> ! {
+        eprintln!("Error: {e:?}");
+        std::process::exit(self as i32)
+    }
+}
 
-fn is_video_file(entry: &DirEntry) -> bool {
-    entry
-        .path()
-        .extension()
+/// How often unchanged directories get a full look, for files rewritten in place
+const DEFAULT_FULL_SCAN_DAYS: u64 = 7;
+
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mkv", "mp4", "avi", "mov", "flv", "wmv", "webm", "m4v", "ts", "m2ts", "mts", "mpg", "mpeg",
+    "vob", "ogv", "3gp", "asf",
+];
+
+fn is_video_file(path: &Path) -> bool {
+    path.extension()
         .map(OsStr::to_string_lossy)
         .map(|ext| {
             let ext = ext.to_ascii_lowercase();
@@ -40,14 +91,14 @@ fn is_video_file(entry: &DirEntry) -> bool {
 fn main() -> JwatchResult<()> {
     color_eyre::install()?;
     let args: Args = argh::from_env();
-    let path = args.path;
-    let jobs = args.jobs.max(1);
-    // --db-path names the exact db file; by default it lives inside the scanned folder
+    let settings = Settings::resolve(&args).unwrap_or_else(|e| ExitStatus::Config.fail(e));
+    // --db-path names the exact db file; by default it lives inside the first scanned folder
     let db_file = args
         .db_path
+        .as_ref()
         .map(PathBuf::from)
-        .unwrap_or_else(|| Path::new(&path).join("jwatch.sqlite"));
-    let cachedb = CacheDB::init_cachedb(&db_file)?;
+        .unwrap_or_else(|| settings.roots[0].path.join("jwatch.sqlite"));
+    let cachedb = CacheDB::init_cachedb(&db_file).unwrap_or_else(|e| ExitStatus::CacheDb.fail(e));
 
     // The handler runs on its own thread and cannot touch the (!Sync) db connection,
     // so it only raises a flag; the loops below stop on it, and the normal
@@ -61,7 +112,9 @@ fn main() -> JwatchResult<()> {
                 // Deliberate second CTRL+C: give up on graceful shutdown. The debounce
                 // matters: one keypress can deliver SIGINT twice in quick succession
                 // (e.g. to both the process and its group), which must not force-quit.
-                Some(t) if t.elapsed() > Duration::from_millis(300) => std::process::exit(130),
+                Some(t) if t.elapsed() > Duration::from_millis(300) => {
+                    std::process::exit(ExitStatus::Interrupted as i32)
+                }
                 Some(_) => {}
                 None => {
                     first_interrupt = Some(Instant::now());
@@ -73,56 +126,791 @@ fn main() -> JwatchResult<()> {
         }
     })?;
 
-    let start = Instant::now();
-    let progress = ProgressBar::new_spinner()
-        .with_message("Indexing media...")
-        .with_elapsed(start.elapsed())
-        .with_style(
-            ProgressStyle::with_template("{spinner} T+{elapsed:<2} | {pos:<5} — {wide_msg}")?
-                .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"),
-        )
-        .with_finish(ProgressFinish::WithMessage(Cow::Borrowed("indexed media")));
-
-    let files: Vec<PathBuf> = WalkDir::new(&path)
-        .into_iter()
-        .take_while(|_| !interrupted.load(Ordering::Relaxed))
-        .filter(|e| e.as_ref().map(is_video_file).unwrap_or(false))
-        .map(|e| e.map(DirEntry::into_path))
-        .progress_with(progress)
-        .collect::<Result<_, _>>()?;
+    let outcome = match settings.command {
+        CommandKind::Scan => scan(&settings, &cachedb, &interrupted),
+        CommandKind::Status => status::status(&settings, &cachedb, &interrupted),
+        CommandKind::Watch => watch::watch(&args, settings, &cachedb, &interrupted),
+    };
+
+    // Also after an error, so the files probed before it stay stored
+    cachedb
+        .cleanup()
+        .unwrap_or_else(|e| ExitStatus::CacheDb.fail(e));
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) if e.downcast_ref::<InvalidLayer>().is_some() => ExitStatus::Config.fail(e),
+        Err(e) => return Err(e),
+    };
+
+    let status = if interrupted.load(Ordering::Relaxed) && !outcome.stopped {
+        ExitStatus::Interrupted
+    } else if outcome.db_errors > 0 {
+        eprintln!("{} file(s) failed to store in the cache", outcome.db_errors);
+        ExitStatus::CacheDb
+    } else if outcome.errors > 0 {
+        eprintln!("{} file(s) failed to process", outcome.errors);
+        ExitStatus::ProbeFailures
+    } else if outcome.failing_findings > 0 {
+        ExitStatus::Findings
+    } else {
+        ExitStatus::Clean
+    };
+    if status != ExitStatus::Clean {
+        std::process::exit(status as i32);
+    }
+
+    Ok(())
+}
+
+/// Everything resolved from the command line and config before any work starts
+struct Settings {
+    /// At least one
+    roots: Vec<Root>,
+    command: CommandKind,
+    jobs: usize,
+    /// Threads listing directories, latency rather than CPU bound on network shares
+    walk_jobs: usize,
+    /// Unchanged directories are listed and their files stat'ed again after this long
+    verify_after: Duration,
+    /// Files modified more recently are likely still being written, and not probed
+    settle: Duration,
+    /// Continue the last interrupted scan
+    resume: bool,
+    /// Which files of a class get probed first
+    order: Order,
+    /// `--sample` and its seed: probe only some files, and extrapolate the summary
+    sample: Option<(SampleSize, u64)>,
+    policy: Policy,
+    /// Rules whose findings fail the run
+    fail_on: Vec<Rule>,
+    /// Known findings from `--baseline`
+    baseline: Option<Baseline>,
+    write_baseline: Option<PathBuf>,
+    /// Guess the language of untagged subtitles from their text
+    detect_subtitles: bool,
+    /// Print mkvpropedit commands tagging the guessed subtitle languages
+    suggest_tagging: bool,
+    /// Detect videos by content too, and check their extensions against it
+    sniff: bool,
+    /// See [`Config::original_languages`]
+    original_languages: BTreeMap<PathBuf, String>,
+}
+
+/// A scanned folder
+struct Root {
+    /// Canonical, so cache rows are tagged the same however the folder was given
+    path: PathBuf,
+    /// `--exclude` globs, relative to this root
+    excludes: Gitignore,
+}
+
+impl Settings {
+    /// Resolves the file's policy and original language and runs the rules, external
+    /// subtitles included. Findings judged by a `.jwatch.toml` layer name it.
+    fn evaluate(&self, file: &Path, mediainfo: &MediaInfo, library: &Library) -> Vec<Finding> {
+        let root = &self.root_of(file).path;
+        let policy = library.layers.policy_for(&self.policy, root, file);
+        let original = original_language::resolve(
+            file,
+            root,
+            library.layers.original_language(file),
+            &self.original_languages,
+            &mediainfo.audio_language,
+        );
+        let external = library.sidecars.subtitles(file);
+        let mut findings = if external.is_empty() {
+            rules::evaluate(mediainfo, &policy, original.as_deref())
+        } else {
+            let mut mediainfo = mediainfo.clone();
+            mediainfo.subtitle_languages.extend_from_slice(external);
+            rules::evaluate(&mediainfo, &policy, original.as_deref())
+        };
+        findings.extend(rules::extension_mismatch(file, mediainfo));
+        findings.retain(|f| !policy.disabled_rules.contains(&f.rule));
+        for finding in &mut findings {
+            if let Some(layer) = policy.set_by.get(&finding.rule) {
+                finding.reason += &format!(" [policy from {layer}]");
+            }
+        }
+        findings
+    }
+
+    /// Index into [`Self::roots`] of the innermost root containing `file`
+    fn root_index(&self, file: &Path) -> usize {
+        self.roots
+            .iter()
+            .enumerate()
+            .filter(|(_, root)| file.starts_with(&root.path))
+            .max_by_key(|(_, root)| root.path.components().count())
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn root_of(&self, file: &Path) -> &Root {
+        &self.roots[self.root_index(file)]
+    }
+
+    /// The full path, so a baseline keeps matching when roots are added or dropped
+    fn baseline_key(&self, file: &Path) -> String {
+        file.to_string_lossy().to_string()
+    }
+
+    fn resolve(args: &Args) -> JwatchResult<Self> {
+        let (paths, command) = match &args.command {
+            Some(Command::Scan(scan)) => (&scan.path, CommandKind::Scan),
+            Some(Command::Status(status)) => (&status.path, CommandKind::Status),
+            Some(Command::Watch(watch)) => (&watch.path, CommandKind::Watch),
+            None => (&args.path, CommandKind::Scan),
+        };
+
+        // An explicit --config must exist, the one inside the (first) scanned folder is optional
+        let config = match (&args.config, paths.first()) {
+            (Some(file), _) => Config::load(Path::new(file))?,
+            (None, Some(path)) if Path::new(path).join(CONFIG_FILE_NAME).exists() => {
+                Config::load(&Path::new(path).join(CONFIG_FILE_NAME))?
+            }
+            (None, _) => Config::default(),
+        };
+        let paths = match paths.is_empty() {
+            true => config.roots.clone(),
+            false => paths.iter().map(PathBuf::from).collect(),
+        };
+        if paths.is_empty() {
+            bail!("missing path to scan and no roots in the config, see --help");
+        }
+        let mut roots = vec![];
+        for path in paths {
+            let path = path
+                .canonicalize()
+                .with_context(|| format!("cannot scan {}", path.display()))?;
+            if roots.iter().any(|r: &Root| r.path == path) {
+                continue;
+            }
+            roots.push(Root {
+                excludes: exclude::build_excludes(&path, &args.exclude)?,
+                path,
+            });
+        }
+        let sample = args
+            .sample
+            .as_deref()
+            .map(|size| Ok::<_, Report>((SampleSize::from_arg(size)?, args.sample_seed)))
+            .transpose()?;
+        let resume = matches!(&args.command, Some(Command::Scan(scan)) if scan.resume);
+        if resume && sample.is_some() {
+            bail!("--sample can't resume a scan, a sample is quick to take again");
+        }
+        let fail_on = match (&args.fail_on, &config.fail_on) {
+            (Some(list), _) => parse_rule_list(&list.split(',').collect::<Vec<_>>())?,
+            (None, Some(list)) => parse_rule_list(list)?,
+            (None, None) => Rule::ALL.to_vec(),
+        };
+
+        Ok(Self {
+            roots,
+            command,
+            jobs: args.jobs.max(1),
+            walk_jobs: args.walk_jobs.max(1),
+            settle: Duration::from_secs(args.settle_secs),
+            resume,
+            order: Order::from_name(&args.order)?,
+            sample,
+            verify_after: match args.full_scan {
+                true => Duration::ZERO,
+                false => Duration::from_secs(
+                    config.full_scan_days.unwrap_or(DEFAULT_FULL_SCAN_DAYS) * 24 * 60 * 60,
+                ),
+            },
+            policy: config.policy()?,
+            fail_on,
+            baseline: args
+                .baseline
+                .as_deref()
+                .map(|file| Baseline::load(Path::new(file)))
+                .transpose()?,
+            write_baseline: args.write_baseline.as_ref().map(PathBuf::from),
+            // Suggestions need guesses, for files probed before detection was enabled too
+            detect_subtitles: args.detect_subtitle_language || args.suggest_tagging,
+            suggest_tagging: args.suggest_tagging,
+            sniff: args.sniff,
+            original_languages: config.original_languages,
+        })
+    }
+}
+
+enum CommandKind {
+    Scan,
+    Status,
+    Watch,
+}
+
+#[derive(Debug, Default)]
+struct RunOutcome {
+    /// Files that failed to probe
+    errors: u32,
+    /// Files that failed to store in the cache
+    db_errors: u32,
+    /// Findings on `--fail-on` rules, with `--baseline` only those missing from it
+    failing_findings: u64,
+    /// Ended by a signal as intended, which `watch` only ever is
+    stopped: bool,
+}
 
+/// Probes everything below the roots, stores it and prints the report
+fn scan(
+    settings: &Settings,
+    cachedb: &CacheDB,
+    interrupted: &Arc<AtomicBool>,
+) -> JwatchResult<RunOutcome> {
+    let progress = probe_progress_bar()?;
+    let (cache, dirs, failures) = cachedb
+        .load_all()
+        .and_then(|cache| Ok((cache, cachedb.load_dirs()?, cachedb.load_failures()?)))
+        .unwrap_or_else(|e| ExitStatus::CacheDb.fail(e));
+    let resume = if settings.resume {
+        cachedb
+            .load_scan_state()
+            .unwrap_or_else(|e| ExitStatus::CacheDb.fail(e))
+    } else {
+        None
+    };
+    if let Some(state) = &resume {
+        let roots = settings.roots.iter().map(|r| &r.path).collect::<Vec<_>>();
+        if state.roots.iter().collect::<Vec<_>>() != roots {
+            let stopped = state.roots.iter().map(|r| r.display().to_string());
+            bail!(
+                "the interrupted scan was of {}, resume it with the same folders",
+                stopped.collect::<Vec<_>>().join(", ")
+            );
+        }
+        println!(
+            "Resuming: {} done, {} files and {} folders left",
+            state.done.len(),
+            state.files.len(),
+            state.dirs.len()
+        );
+    } else if settings.resume {
+        eprintln!("No interrupted scan to resume, scanning everything");
+    }
+
+    let mut results: Vec<(PathBuf, MediaInfo)> = vec![];
+    let mut in_progress = vec![];
+    let mut outcome = RunOutcome::default();
+    let mut tallies = settings
+        .roots
+        .iter()
+        .map(|_| Tally::default())
+        .collect::<Vec<_>>();
+
+    let library = walk_and_probe(
+        settings,
+        &cache,
+        &dirs,
+        &failures,
+        resume.as_ref(),
+        interrupted,
+        &progress,
+        |file, probed| {
+            let root = settings.root_index(file);
+            match probed {
+                ProbeOutcome::Skipped => {}
+                ProbeOutcome::Cached(info) => {
+                    tallies[root].files_total += 1;
+                    results.push((file.to_path_buf(), info));
+                }
+                ProbeOutcome::Fresh(info) | ProbeOutcome::Updated(info) => {
+                    tallies[root].files_total += 1;
+                    let root = &settings.roots[root].path;
+                    if let Err(e) = cachedb.store_to_cachedb(file, root, &info) {
+                        progress.println(format!("cachedb: {:?}: {}", e, file.display()));
+                        outcome.db_errors += 1;
+                    }
+                    results.push((file.to_path_buf(), info));
+                }
+                ProbeOutcome::Failed(e, failure) => {
+                    tallies[root].files_total += 1;
+                    progress.println(format!("{:?}: {}", e, file.display()));
+                    outcome.errors += 1;
+                    let stored =
+                        failure.map_or(Ok(()), |f| cachedb.store_failure(file, f, &e.to_string()));
+                    if let Err(e) = stored {
+                        progress.println(format!("cachedb: {:?}: {}", e, file.display()));
+                        outcome.db_errors += 1;
+                    }
+                }
+                ProbeOutcome::InProgress => {
+                    tallies[root].in_progress += 1;
+                    in_progress.push(file.to_path_buf());
+                }
+            }
+        },
+    )?;
+    progress.finish_using_style();
+    for (dir, listing) in &library.listings {
+        if let Err(e) = cachedb.store_dir(dir, listing) {
+            eprintln!("cachedb: {:?}: {}", e, dir.display());
+            outcome.db_errors += 1;
+        }
+    }
+    // A whole walk saw all there is in the folders it went through, what it missed is gone
+    if !interrupted.load(Ordering::Relaxed) && settings.sample.is_none() {
+        let seen = library.files.iter().collect::<HashSet<_>>();
+        let gone = cache
+            .keys()
+            .chain(failures.keys())
+            .filter(|file| {
+                !seen.contains(file)
+                    && file
+                        .parent()
+                        .is_some_and(|dir| library.walked.contains(dir))
+            })
+            .collect::<BTreeSet<_>>();
+        for file in gone {
+            if let Err(e) = cachedb.remove(file) {
+                eprintln!("cachedb: {:?}: {}", e, file.display());
+                outcome.db_errors += 1;
+            }
+        }
+    }
+    let remembered = match (&library.unfinished, settings.sample) {
+        (Some(state), _) => cachedb.store_scan_state(state),
+        // Leaves an interrupted full scan for --resume
+        (None, Some(_)) => Ok(()),
+        (None, None) => cachedb.clear_scan_state(),
+    };
+    if let Err(e) = remembered {
+        eprintln!("cachedb: {e:?}");
+        outcome.db_errors += 1;
+    }
+    // Probes finish in any order, the report shouldn't
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // Root index, finding, filename
+    let mut reports = vec![];
+    let mut snapshot = Baseline::default();
+    let mut tagging = vec![];
+    for (file, mediainfo) in &results {
+        let root = settings.root_index(file);
+        let filename = file
+            .file_name()
+            .context("missing file path")?
+            .to_string_lossy()
+            .to_string();
+        let key = settings.baseline_key(file);
+
+        if settings.suggest_tagging {
+            tagging.extend(langdetect::tagging_commands(
+                file,
+                &mediainfo.subtitle_languages,
+            ));
+        }
+
+        let findings = settings.evaluate(file, mediainfo, &library);
+        if let Err(e) = cachedb.store_findings(file, &findings) {
+            eprintln!("cachedb: {:?}: {}", e, file.display());
+            outcome.db_errors += 1;
+        }
+        let mut saved = Savings::default();
+        for finding in &findings {
+            saved.add(finding);
+        }
+        if !findings.is_empty() {
+            tallies[root].files_non_ideal += 1;
+            tallies[root].file_savings.push(saved);
+        }
+        tallies[root].savings.merge(&saved);
+        for finding in findings {
+            snapshot.insert(&key, finding.rule);
+            if let Some(known) = &settings.baseline
+                && known.contains(&key, finding.rule)
+            {
+                continue;
+            }
+            if settings.fail_on.contains(&finding.rule) {
+                outcome.failing_findings += 1;
+            }
+            tallies[root].new_findings += 1;
+            reports.push((root, finding, filename.clone()));
+        }
+    }
+    for (file, size) in &library.sidecars.orphans {
+        let tally = &mut tallies[settings.root_index(file)];
+        tally.orphans += 1;
+        tally.orphaned_bytes += size;
+    }
+
+    // Stable, so findings stay sorted by path within a root
+    reports.sort_by_key(|(root, _, _)| *root);
+    let per_root = settings.roots.len() > 1;
+    let mut current_root = None;
+    for (root, finding, filename) in &reports {
+        if per_root && current_root != Some(*root) {
+            println!("Findings in {}:", settings.roots[*root].path.display());
+            current_root = Some(*root);
+        }
+        println!("{} found in: {filename}", finding.reason);
+    }
+
+    let orphans = &library.sidecars.orphans;
+    if !orphans.is_empty() {
+        println!("Orphaned sidecars (no matching video):");
+        for (file, size) in orphans {
+            println!("\t{} ({})", file.display(), HumanBytes(*size));
+        }
+    }
+
+    if !in_progress.is_empty() {
+        in_progress.sort();
+        println!("Still being written, checked next time:");
+        for file in &in_progress {
+            println!("\t{}", file.display());
+        }
+    }
+
+    if !tagging.is_empty() {
+        println!("Suggested subtitle tagging:");
+        for command in &tagging {
+            println!("\t{command}");
+        }
+    }
+
+    if interrupted.load(Ordering::Relaxed) {
+        println!("Scan interrupted, results are partial");
+        if library.unfinished.is_some() {
+            println!("Continue it with `jwatch scan --resume`");
+        }
+    }
+    if let Some((_, seed)) = settings.sample {
+        for file in &library.files {
+            *tallies[settings.root_index(file)]
+                .population
+                .get_or_insert(0) += 1;
+        }
+        let sampled = tallies.iter().map(|t| t.files_total).sum::<u64>();
+        println!(
+            "ESTIMATE: checked a sample of {sampled} of {} files (seed {seed}), the summary \
+             extrapolates it to all of them with 95% confidence intervals",
+            library.files.len()
+        );
+    }
+    let summary = match settings.sample {
+        Some(_) => "Estimated summary",
+        None => "Summary",
+    };
+    if per_root {
+        let mut total = Tally::default();
+        for (root, tally) in settings.roots.iter().zip(&tallies) {
+            println!("{summary} for {}:", root.path.display());
+            tally.print(settings);
+            total.merge(tally);
+        }
+        println!("{summary} (all roots):");
+        total.print(settings);
+    } else {
+        println!("{summary}:");
+        tallies[0].print(settings);
+    }
+
+    if let Some(file) = &settings.write_baseline {
+        if interrupted.load(Ordering::Relaxed) {
+            eprintln!("Not writing baseline, the scan is incomplete");
+        } else if settings.sample.is_some() {
+            eprintln!("Not writing baseline, only a sample was checked");
+        } else {
+            snapshot.write(file)?;
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Summary figures of one root, or of all of them
+#[derive(Debug, Default)]
+struct Tally {
+    files_total: u64,
+    files_non_ideal: u64,
+    savings: Savings,
+    orphans: usize,
+    orphaned_bytes: u64,
+    /// Still being written, not probed
+    in_progress: u64,
+    /// Reported findings, with `--baseline` only those missing from it
+    new_findings: usize,
+    /// Savings of each non-ideal file, for extrapolating a sample
+    file_savings: Vec<Savings>,
+    /// With `--sample`, all files found, of which `files_total` were checked
+    population: Option<usize>,
+}
+
+impl Tally {
+    fn merge(&mut self, other: &Tally) {
+        self.files_total += other.files_total;
+        self.files_non_ideal += other.files_non_ideal;
+        self.savings.merge(&other.savings);
+        self.orphans += other.orphans;
+        self.orphaned_bytes += other.orphaned_bytes;
+        self.in_progress += other.in_progress;
+        self.new_findings += other.new_findings;
+        self.file_savings.extend_from_slice(&other.file_savings);
+        if let Some(population) = other.population {
+            *self.population.get_or_insert(0) += population;
+        }
+    }
+
+    fn print(&self, settings: &Settings) {
+        if let Some(population) = self.population {
+            return self.print_estimate(settings, population);
+        }
+        let savings = &self.savings;
+        println!(
+            "\tNon-ideal files: {}/{}",
+            self.files_non_ideal, self.files_total
+        );
+        println!("\tMinimum savings:");
+        println!(
+            "\t\tVideo:     {}",
+            savings_figure(savings.video, savings.video_estimated)
+        );
+        println!(
+            "\t\tAudio:     {}",
+            savings_figure(savings.audio, savings.audio_estimated)
+        );
+        println!(
+            "\t\tSubtitles: {}",
+            savings_figure(savings.subtitles, savings.subtitles_estimated)
+        );
+        println!(
+            "\t\tTotal:     {}",
+            savings_figure(savings.total(), savings.total_estimated())
+        );
+        if self.orphans > 0 {
+            println!(
+                "\tOrphaned sidecars: {} ({} reclaimable)",
+                self.orphans,
+                HumanBytes(self.orphaned_bytes)
+            );
+        }
+        if self.in_progress > 0 {
+            println!("\tIn progress (not checked): {}", self.in_progress);
+        }
+        if settings.baseline.is_some() {
+            println!("\tNew findings (not in baseline): {}", self.new_findings);
+        }
+    }
+
+    /// Like [`Self::print`], extrapolated from the sampled files to all `population` of them
+    fn print_estimate(&self, settings: &Settings, population: usize) {
+        let sampled = self.files_total as usize;
+        let non_ideal = Estimate::count(self.files_non_ideal as usize, sampled, population);
+        println!(
+            "\tNon-ideal files: ~{:.0}/{population} ({}, {sampled} checked)",
+            non_ideal.value,
+            interval(non_ideal, |v| format!("{v:.0}"))
+        );
+        let saved = |part: fn(&Savings) -> u64| {
+            let values = self.file_savings.iter().map(|s| part(s) as f64);
+            let estimate = Estimate::total(values, sampled, population);
+            let bytes = |v: f64| HumanBytes(v as u64).to_string();
+            format!("~{} ({})", bytes(estimate.value), interval(estimate, bytes))
+        };
+        println!("\tMinimum savings:");
+        println!("\t\tVideo:     {}", saved(|s| s.video));
+        println!("\t\tAudio:     {}", saved(|s| s.audio));
+        println!("\t\tSubtitles: {}", saved(|s| s.subtitles));
+        println!("\t\tTotal:     {}", saved(Savings::total));
+        // The walk saw every sidecar, these are exact
+        if self.orphans > 0 {
+            println!(
+                "\tOrphaned sidecars: {} ({} reclaimable)",
+                self.orphans,
+                HumanBytes(self.orphaned_bytes)
+            );
+        }
+        if self.in_progress > 0 {
+            println!(
+                "\tIn progress (not checked, of the sample): {}",
+                self.in_progress
+            );
+        }
+        if settings.baseline.is_some() {
+            println!(
+                "\tNew findings (not in baseline, of the sample): {}",
+                self.new_findings
+            );
+        }
+    }
+}
+
+/// e.g. "1.10 TiB to 1.30 TiB"
+fn interval(estimate: Estimate, show: impl Fn(f64) -> String) -> String {
+    match estimate.high.is_finite() {
+        true => format!("{} to {}", show(estimate.low), show(estimate.high)),
+        false => format!(
+            "at least {}, the sample is too small or uniform for a range",
+            show(estimate.low)
+        ),
+    }
+}
+
+/// e.g. "1.20 GiB (300 MiB estimated)"
+fn savings_figure(saved: u64, estimated: u64) -> String {
+    match estimated {
+        0 => HumanBytes(saved).to_string(),
+        _ => format!(
+            "{} ({} estimated)",
+            HumanBytes(saved),
+            HumanBytes(estimated)
+        ),
+    }
+}
+
+/// What the walk found below the scanned folder
+struct Library {
+    /// Video files and disc folder titles, sorted
+    files: Vec<PathBuf>,
+    sidecars: Sidecars,
+    layers: PolicyLayers,
+    /// Directories listed from disk, for the next walk to skip if unchanged
+    listings: Vec<(PathBuf, DirListing)>,
+    /// Every directory the walk went through, from disk or the cache
+    walked: HashSet<PathBuf>,
+    /// Where the walk and probes stopped, if interrupted
+    unfinished: Option<ScanState>,
+}
+
+/// Collected by the walker threads
+#[derive(Default)]
+struct Found {
+    files: Vec<PathBuf>,
+    sidecar_files: Vec<PathBuf>,
+    layers: PolicyLayers,
+    listings: Vec<(PathBuf, DirListing)>,
+    walked: HashSet<PathBuf>,
+    /// Titles of discs with both a `BDMV` and a `VIDEO_TS` folder are found twice
+    disc_titles: HashSet<PathBuf>,
+}
+
+fn probe_progress_bar() -> JwatchResult<ProgressBar> {
     let start = Instant::now();
-    let progress = ProgressBar::new(files.len() as u64)
+    // The walk grows the length as it finds files
+    let progress = ProgressBar::new(0)
         .with_elapsed(start.elapsed())
+        .with_message("indexing media...")
         .with_style(ProgressStyle::with_template(
-            "{spinner} T+{elapsed:<2} T-{eta:<2} {bar:60.cyan/red} {pos:>5}/{len:<5} {wide_msg}"
+            "{spinner} T+{elapsed:<2} T-{eta:<2} {bar:60.cyan/red} {pos:>5}/{len:<5} {prefix} {wide_msg}"
         )?.tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"))
         .with_finish(ProgressFinish::WithMessage(Cow::Borrowed("processed all media")));
     progress.enable_steady_tick(Duration::from_millis(50));
+    Ok(progress)
+}
 
-    let cache = cachedb.load_all()?;
-
-    let mut results: Vec<Option<MediaInfo>> = Vec::new();
-    results.resize_with(files.len(), || None);
-    let mut errors = 0u32;
-    let mut files_total = 0u64;
-
-    let next_file = AtomicUsize::new(0);
+/// Walks the roots on `--walk-jobs` threads and runs [`probe_one`] on `--jobs` worker
+/// threads as soon as a video is found, new ones before changed, cached and failed ones.
+/// Every outcome goes to `on_outcome` on the calling
+/// thread, which is where all DB access has to stay. Sidecars and policy layers are only
+/// complete once the walk is, so the returned [`Library`] is what findings need.
+/// Failures caused by an interrupt are swallowed, an invalid `.jwatch.toml` is a config error.
+/// With `resume`, continues where that scan stopped, its probed files served from the cache.
+#[allow(clippy::too_many_arguments)]
+fn walk_and_probe(
+    settings: &Settings,
+    cache: &HashMap<PathBuf, MediaInfo>,
+    dirs: &HashMap<PathBuf, DirListing>,
+    failures: &HashMap<PathBuf, Failure>,
+    resume: Option<&ScanState>,
+    interrupted: &Arc<AtomicBool>,
+    progress: &ProgressBar,
+    mut on_outcome: impl FnMut(&Path, ProbeOutcome),
+) -> JwatchResult<Library> {
+    let queue = ProbeQueue::new(settings.order, cache, failures, progress);
+    let mut found = Found::default();
+    if let Some(state) = resume {
+        for dir in &state.layer_dirs {
+            let layer = dir.join(LAYER_FILE_NAME);
+            if layer.is_file() {
+                found
+                    .layers
+                    .insert(dir.clone(), Config::load_layer(&layer)?);
+            }
+        }
+        found.sidecar_files = state.sidecar_files.clone();
+        // Done ones are cached, and quickly counted again
+        let queued = state.done.iter().map(|f| (f, true));
+        let queued = queued.chain(state.files.iter().map(|f| (f, false)));
+        for (file, trusted) in queued {
+            found.files.push(file.clone());
+            found.disc_titles.insert(file.clone());
+            progress.inc_length(1);
+            queue.push(file.clone(), trusted);
+        }
+    }
+    let found = Mutex::new(found);
+    let walk_error = Mutex::new(None);
+    let unlisted = Mutex::new(vec![]);
+    // Stops the probes too, a failed walk is a failed run
+    let walk_failed = AtomicBool::new(false);
     let (tx, rx) = mpsc::channel();
+    // Handled outcomes, and probes cut short by the interrupt
+    let mut done = vec![];
+    let mut cut_short = vec![];
     std::thread::scope(|scope| {
-        for _ in 0..jobs {
+        let indexer = Indexer {
+            settings,
+            found: &found,
+            queue: &queue,
+            progress,
+        };
+        let (walk_error, walk_failed, unlisted) = (&walk_error, &walk_failed, &unlisted);
+        let found = &found;
+        let queue = &queue;
+        scope.spawn(move || {
+            let walked = walk::walk(
+                &settings.roots,
+                resume.map(|state| state.dirs.as_slice()),
+                settings.walk_jobs,
+                dirs,
+                settings.verify_after,
+                interrupted,
+                &indexer,
+            );
+            match walked {
+                Ok(_) if interrupted.load(Ordering::Relaxed) && settings.sample.is_some() => {}
+                Ok(_) if let Some((size, seed)) = settings.sample => {
+                    let files = found.lock().unwrap().files.clone();
+                    for file in sample::pick(files, size, seed) {
+                        progress.inc_length(1);
+                        queue.push(file, false);
+                    }
+                }
+                Ok(pending) => *unlisted.lock().unwrap() = pending,
+                Err(e) => {
+                    *walk_error.lock().unwrap() = Some(e);
+                    walk_failed.store(true, Ordering::Relaxed);
+                }
+            }
+            // Lets the probes drain the queue and quit
+            queue.close();
+        });
+
+        for _ in 0..settings.jobs {
             let tx = tx.clone();
-            let progress = progress.clone();
-            let interrupted = interrupted.clone();
-            let (files, cache, next_file) = (&files, &cache, &next_file);
             scope.spawn(move || {
                 loop {
-                    if interrupted.load(Ordering::Relaxed) {
+                    if interrupted.load(Ordering::Relaxed) || walk_failed.load(Ordering::Relaxed) {
                         break;
                     }
-                    let i = next_file.fetch_add(1, Ordering::Relaxed);
-                    let Some(path) = files.get(i) else { break };
-                    if tx.send((i, probe_one(path, cache, &progress))).is_err() {
+                    // Ends once the walk is done and the queue drained
+                    let Some(next) = queue.pop() else {
+                        break;
+                    };
+                    let outcome = probe_one(
+                        &next.path,
+                        next.trusted,
+                        next.metadata,
+                        cache,
+                        settings,
+                        progress,
+                    );
+                    if tx.send((next.path, outcome)).is_err() {
                         break;
                     }
                 }
@@ -131,143 +919,117 @@ fn main() -> JwatchResult<()> {
         // The workers hold the remaining senders; the loop below ends when they finish
         drop(tx);
 
-        // All DB access stays on this thread — the connection is !Sync
-        for (i, outcome) in rx {
+        for (path, outcome) in rx {
             progress.inc(1);
-            match outcome {
-                ProbeOutcome::Skipped => {}
-                ProbeOutcome::Cached(info) => {
-                    files_total += 1;
-                    results[i] = Some(info);
-                }
-                ProbeOutcome::Fresh(info) => {
-                    files_total += 1;
-                    if let Err(e) = cachedb.store_to_cachedb(&files[i], &info) {
-                        progress.println(format!("cachedb: {:?}: {}", e, files[i].display()));
-                        errors += 1;
-                    }
-                    results[i] = Some(info);
-                }
-                ProbeOutcome::Failed(e) => {
-                    if interrupted.load(Ordering::Relaxed) {
-                        // The terminal delivers SIGINT to the mediainfo children too,
-                        // so failures after the interrupt are our own doing, not bad files
-                        continue;
-                    }
-                    files_total += 1;
-                    progress.println(format!("{:?}: {}", e, files[i].display()));
-                    errors += 1;
-                }
+            if matches!(outcome, ProbeOutcome::Failed(..)) && interrupted.load(Ordering::Relaxed) {
+                // The terminal delivers SIGINT to the mediainfo children too,
+                // so failures after the interrupt are our own doing, not bad files
+                cut_short.push(path);
+                continue;
             }
+            on_outcome(&path, outcome);
+            done.push(path);
         }
     });
-    progress.finish_using_style();
-
-    let mut reports = vec![];
-    let mut files_non_ideal = 0u64;
-    let mut saved_video = 0u64;
-    let mut saved_audio = 0u64;
-    let mut saved_subs = 0u64;
-    for (path, mediainfo) in files.iter().zip(&results) {
-        let Some(mediainfo) = mediainfo else {
-            continue;
-        };
-        {
-            let reports_before = reports.len();
-            let filename = path
-                .file_name()
-                .context("missing file path")?
-                .to_string_lossy()
-                .to_string();
-
-            if !ACCEPTED_BITRATE_RANGE.contains(&mediainfo.megabitrate()) {
-                let reason = format!(
-                    "Undesired bitrate: {:<4.1} mbit/s with codec {:<4}",
-                    mediainfo.megabitrate(),
-                    mediainfo.codec,
-                );
-                if mediainfo.megabitrate() >= ACCEPTED_BITRATE_RANGE.end {
-                    let max_bytes_per_sec = ACCEPTED_BITRATE_RANGE.end * 2.0_f64.powi(20) / 8.0;
-                    let bytes_per_sec = mediainfo.bitrate as f64 / 8.0;
-                    saved_video += ((bytes_per_sec - max_bytes_per_sec)
-                        * mediainfo.duration.as_secs_f64())
-                        as u64;
-                }
-                reports.push((reason, filename.clone(), mediainfo.clone()));
-            }
+    if let Some(e) = walk_error.into_inner().unwrap() {
+        return Err(e);
+    }
 
-            let desired_langs = ACCEPTED_LANGS;
-            let undesired = mediainfo
-                .audio_language
-                .iter()
-                .filter(|t| !desired_langs.contains(&t.language.as_str()))
-                .collect::<Vec<_>>();
-            if !undesired.is_empty() {
-                saved_audio += undesired.iter().map(|t| t.size).sum::<u64>();
-                let langs = undesired
-                    .iter()
-                    .map(|t| t.language.as_str())
-                    .collect::<Vec<_>>();
-                reports.push((
-                    format!("Undesired languages {}", langs.join(" ")),
-                    filename.clone(),
-                    mediainfo.clone(),
-                ));
-            }
+    let Found {
+        mut files,
+        mut sidecar_files,
+        layers,
+        listings,
+        walked,
+        ..
+    } = found.into_inner().unwrap();
+    // A sample is quick to take again
+    let resumable = interrupted.load(Ordering::Relaxed) && settings.sample.is_none();
+    let unfinished = resumable.then(|| {
+        cut_short.extend(queue.drain());
+        ScanState {
+            roots: settings.roots.iter().map(|r| r.path.clone()).collect(),
+            done,
+            files: cut_short,
+            dirs: unlisted.into_inner().unwrap(),
+            sidecar_files: sidecar_files.clone(),
+            layer_dirs: layers.dirs().cloned().collect(),
+        }
+    });
+    files.sort();
+    sidecar_files.sort();
+    let sidecars = Sidecars::associate(&files, sidecar_files, settings.detect_subtitles);
+    Ok(Library {
+        files,
+        sidecars,
+        layers,
+        listings,
+        walked,
+        unfinished,
+    })
+}
 
-            let undesired_subs = mediainfo
-                .subtitle_languages
-                .iter()
-                .filter(|t| !desired_langs.contains(&t.language.as_str()))
-                .collect::<Vec<_>>();
-            if !undesired_subs.is_empty() {
-                saved_subs += undesired_subs.iter().map(|t| t.size).sum::<u64>();
-                let langs = undesired_subs
-                    .iter()
-                    .map(|t| t.language.as_str())
-                    .collect::<Vec<_>>();
-                reports.push((
-                    format!("Undesired subtitle languages {}", langs.join(" ")),
-                    filename.clone(),
-                    mediainfo.clone(),
-                ));
-            }
+/// Sorts what the walk finds, and hands videos to the probes as soon as they turn up
+struct Indexer<'a> {
+    settings: &'a Settings,
+    found: &'a Mutex<Found>,
+    queue: &'a ProbeQueue<'a>,
+    progress: &'a ProgressBar,
+}
 
-            if reports.len() > reports_before {
-                files_non_ideal += 1;
-            }
+impl Indexer<'_> {
+    fn probe(&self, file: PathBuf, trusted: bool) {
+        self.found.lock().unwrap().files.push(file.clone());
+        // Picked from everything found once the walk is done
+        if self.settings.sample.is_some() {
+            return;
         }
+        self.progress.inc_length(1);
+        self.queue.push(file, trusted);
     }
+}
 
-    for (reason, filename, _mediainfo) in reports {
-        println!("{} found in: {filename}", reason);
+impl walk::Visitor for Indexer<'_> {
+    fn dir(&self, dir: &Path, entries: &[walk::DirEntry], trusted: bool) -> JwatchResult<bool> {
+        self.found.lock().unwrap().walked.insert(dir.to_path_buf());
+        if entries.iter().any(|e| !e.dir && e.name == LAYER_FILE_NAME) {
+            let config = Config::load_layer(&dir.join(LAYER_FILE_NAME))?;
+            self.found
+                .lock()
+                .unwrap()
+                .layers
+                .insert(dir.to_path_buf(), config);
+        }
+        // A disc is one title, its streams are fragments of it
+        if let Some(title) = disc::title_dir(dir) {
+            if self
+                .found
+                .lock()
+                .unwrap()
+                .disc_titles
+                .insert(title.to_path_buf())
+            {
+                self.probe(title.to_path_buf(), trusted);
+            }
+            return Ok(false);
+        }
+        Ok(true)
     }
 
-    if interrupted.load(Ordering::Relaxed) {
-        println!("Scan interrupted, results are partial");
+    fn file(&self, file: PathBuf, trusted: bool) -> JwatchResult<()> {
+        if is_video_file(&file) {
+            self.probe(file, trusted);
+        } else if sidecar::is_sidecar_file(&file) {
+            self.found.lock().unwrap().sidecar_files.push(file);
+        } else if self.settings.sniff && matches!(sniff::sniff(&file), Ok(Some(_))) {
+            self.probe(file, trusted);
+        }
+        Ok(())
     }
-    println!("Summary:");
-    println!("\tNon-ideal files: {files_non_ideal}/{files_total}");
-    println!("\tMinimum savings:");
-    println!("\t\tVideo:     {}", HumanBytes(saved_video));
-    println!("\t\tAudio:     {}", HumanBytes(saved_audio));
-    println!("\t\tSubtitles: {}", HumanBytes(saved_subs));
-    println!(
-        "\t\tTotal:     {}",
-        HumanBytes(saved_video + saved_audio + saved_subs)
-    );
 
-    cachedb.cleanup()?;
-
-    if errors > 0 {
-        bail!("{errors} file(s) failed to process");
+    fn listed(&self, dir: PathBuf, listing: DirListing) {
+        self.found.lock().unwrap().listings.push((dir, listing));
     }
-    if interrupted.load(Ordering::Relaxed) {
-        // Conventional exit code for SIGINT
-        std::process::exit(130);
-    }
-
-    Ok(())
 }
 
 enum ProbeOutcome {
@@ -275,46 +1037,124 @@ enum ProbeOutcome {
     Skipped,
     /// Served from the preloaded cache
     Cached(MediaInfo),
+    /// Served from the cache, but gained subtitle language guesses that still need storing
+    Updated(MediaInfo),
     /// Probed with mediainfo, still needs storing
     Fresh(MediaInfo),
-    Failed(Report),
+    /// Modified within `--settle-secs` or while being probed, deliberately not cached
+    InProgress,
+    /// With the file as the probe saw it, `None` if it couldn't even stat it
+    Failed(Report, Option<Failure>),
 }
 
 /// Runs on worker threads: stat, cache lookup, mediainfo probe. No DB access.
+/// `trusted` files are served from the cache as they are, without a stat. `metadata`
+/// saves the stat if the caller has one already.
 fn probe_one(
     path: &Path,
-    cache: &HashMap<String, MediaInfo>,
+    trusted: bool,
+    metadata: Option<std::fs::Metadata>,
+    cache: &HashMap<PathBuf, MediaInfo>,
+    settings: &Settings,
     progress: &ProgressBar,
 ) -> ProbeOutcome {
-    let metadata = match std::fs::metadata(path) {
+    let cached = cache.get(path);
+    if trusted && let Some(info) = cached {
+        return serve_cached(path, info, settings);
+    }
+
+    let metadata = match metadata.map_or_else(|| std::fs::metadata(path), Ok) {
         Ok(m) => m,
-        Err(e) => return ProbeOutcome::Failed(eyre!("stat: {e}")),
+        Err(e) => return ProbeOutcome::Failed(eyre!("stat: {e}"), None),
+    };
+    let failed = |e| ProbeOutcome::Failed(e, Some(Failure::from_metadata(&metadata)));
+    // Directories in the file list are disc folder titles
+    let disc = match metadata.is_dir() {
+        true => match Disc::open(path) {
+            Ok(Some(disc)) => Some(disc),
+            Ok(None) => return ProbeOutcome::Skipped,
+            Err(e) => return failed(e),
+        },
+        false if !metadata.is_file() => return ProbeOutcome::Skipped,
+        false => None,
     };
-    if !metadata.is_file() {
-        return ProbeOutcome::Skipped;
-    }
     if let Some(name) = path.file_name() {
         progress.set_message(format!("processing {}", name.display()));
     }
 
-    let mtime = match metadata
-        .modified()
-        .map_err(Report::new)
-        .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).map_err(Report::new))
-    {
+    let mtime = match metadata.modified().map_err(Report::new).and_then(|m| {
+        m.duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(Report::new)
+    }) {
         Ok(d) => d.as_secs() as i64,
-        Err(e) => return ProbeOutcome::Failed(e),
+        Err(e) => return failed(e),
+    };
+    let (mtime, size) = match &disc {
+        Some(disc) => (disc.mtime, disc.size),
+        None => (mtime, metadata.len()),
     };
-    if let Some(info) = path
-        .file_name()
-        .and_then(|n| cache.get(&*n.to_string_lossy()))
+    if let Some(info) = cached
         && info.mtime == mtime
+        && info.size as u64 == size
     {
-        return ProbeOutcome::Cached(info.clone());
+        return serve_cached(path, info, settings);
     }
+    // A download or remux still running: mediainfo would fail, or see a truncated file
+    let age = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+        - mtime;
+    if age < settings.settle.as_secs() as i64 {
+        return ProbeOutcome::InProgress;
+    }
+
+    let probed = match &disc {
+        Some(disc) => disc.probe(),
+        None => probe_mediainfo(path, &metadata),
+    };
+    let mut info = match probed {
+        Ok(info) => info,
+        Err(e) => return failed(e),
+    };
+    // Written to while mediainfo read it, what it saw may be incomplete
+    if disc.is_none() {
+        match std::fs::metadata(path) {
+            Ok(after)
+                if after.len() == metadata.len()
+                    && after.modified().ok() == metadata.modified().ok() => {}
+            Ok(_) => return ProbeOutcome::InProgress,
+            Err(e) => return failed(eyre!("stat: {e}")),
+        }
+    }
+    info.container = sniff_container(path, settings);
+    if settings.detect_subtitles
+        && let Err(e) = langdetect::detect_untagged(path, &mut info.subtitle_languages)
+    {
+        return failed(e);
+    }
+    ProbeOutcome::Fresh(info)
+}
+
+/// The cache entry, with guesses for subtitles untagged since it was stored
+fn serve_cached(path: &Path, info: &MediaInfo, settings: &Settings) -> ProbeOutcome {
+    let mut info = info.clone();
+    info.container = sniff_container(path, settings);
+    if !settings.detect_subtitles {
+        return ProbeOutcome::Cached(info);
+    }
+    match langdetect::detect_untagged(path, &mut info.subtitle_languages) {
+        Ok(true) => ProbeOutcome::Updated(info),
+        Ok(false) => ProbeOutcome::Cached(info),
+        // Cached files need no failure row, a change gets them probed anyway
+        Err(e) => ProbeOutcome::Failed(e, None),
+    }
+}
 
-    match probe_mediainfo(path, &metadata) {
-        Ok(info) => ProbeOutcome::Fresh(info),
-        Err(e) => ProbeOutcome::Failed(e),
+/// With `--sniff`. Unreadable files fail in mediainfo, or are fine when cached.
+fn sniff_container(path: &Path, settings: &Settings) -> Option<Container> {
+    match settings.sniff {
+        true => sniff::sniff(path).ok().flatten(),
+        false => None,
     }
 }
@@ -1,5 +1,6 @@
 use crate::JwatchResult;
-use crate::metastructs::{Codec, LangTrack, MediaInfo};
+use crate::language;
+use crate::metastructs::{Codec, LangTrack, MediaInfo, SizeSource};
 use color_eyre::eyre::{ContextCompat, bail, eyre};
 use serde::Deserialize;
 use std::fs::Metadata;
@@ -40,19 +41,148 @@ struct Track {
     language: Option<String>,
     #[serde(rename = "StreamSize")]
     stream_size: Option<String>,
+    #[serde(rename = "Title")]
+    title: Option<String>,
+    /// "Yes" or "No", like the other flags
+    #[serde(rename = "Default")]
+    default: Option<String>,
+    #[serde(rename = "Forced")]
+    forced: Option<String>,
+    #[serde(rename = "HearingImpaired")]
+    hearing_impaired: Option<String>,
+    /// e.g. "XLL" for DTS-HD MA, "JOC" for E-AC-3 Atmos
+    #[serde(rename = "Format_AdditionalFeatures")]
+    format_additional_features: Option<String>,
+    /// e.g. "Dolby TrueHD with Dolby Atmos", "DTS-HD Master Audio"
+    #[serde(rename = "Format_Commercial_IfAny")]
+    format_commercial: Option<String>,
+    #[serde(rename = "Channels")]
+    channels: Option<String>,
+    #[serde(rename = "ChannelLayout")]
+    channel_layout: Option<String>,
+    #[serde(rename = "BitRate")]
+    bit_rate: Option<String>,
+    /// AC-3 bitstream mode, "CO" for commentary
+    #[serde(rename = "ServiceKind")]
+    service_kind: Option<String>,
+    /// Index of the track's stream in the container, e.g. "2". Captions carried inside
+    /// the video stream have none, or one like "0-1".
+    #[serde(rename = "StreamOrder")]
+    stream_order: Option<String>,
 }
 
 impl Track {
-    fn to_lang_track(&self) -> Option<LangTrack> {
-        Some(LangTrack {
-            language: self.language.clone()?,
-            size: self
-                .stream_size
+    /// Untagged tracks are kept with [`language::UNDETERMINED`].
+    /// `file_duration` stands in for tracks without their own Duration.
+    fn to_lang_track(&self, file_duration: Duration) -> LangTrack {
+        let (size, size_source) = self.size(file_duration);
+        LangTrack {
+            language: self
+                .language
                 .as_deref()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0),
-        })
+                .map(language::normalize)
+                .unwrap_or_else(|| language::UNDETERMINED.to_owned()),
+            size,
+            size_source,
+            format: self.format.clone(),
+            title: self.title.clone(),
+            default: self.default.as_deref() == Some("Yes"),
+            forced: self.forced.as_deref() == Some("Yes"),
+            sdh: self.hearing_impaired.as_deref() == Some("Yes")
+                || self.title.as_deref().is_some_and(is_sdh_title),
+            channels: self.channels.as_deref().and_then(|c| c.parse().ok()),
+            channel_layout: self.channel_layout.clone(),
+            bitrate: self.bit_rate.as_deref().and_then(|b| b.parse().ok()),
+            lossless: self.is_lossless(),
+            object_audio: self.is_object_audio(),
+            commentary: self.service_kind.as_deref() == Some("CO")
+                || self
+                    .title
+                    .as_deref()
+                    .is_some_and(|t| t.to_ascii_lowercase().contains("commentary")),
+            detected: None,
+            external: false,
+            stream: self.stream(),
+        }
     }
+
+    /// The container stream the track is, `None` for EIA-608/708 captions inside the video
+    fn stream(&self) -> Option<u32> {
+        if self
+            .format
+            .as_deref()
+            .is_some_and(|f| f.starts_with("EIA-"))
+        {
+            return None;
+        }
+        self.stream_order.as_deref()?.parse().ok()
+    }
+
+    /// StreamSize, otherwise BitRate times Duration, otherwise a guess from the codec
+    fn size(&self, file_duration: Duration) -> (u64, SizeSource) {
+        if let Some(size) = self.stream_size.as_deref().and_then(|s| s.parse().ok()) {
+            return (size, SizeSource::StreamSize);
+        }
+        let seconds = self
+            .duration
+            .as_deref()
+            .and_then(|d| d.parse::<f64>().ok())
+            .unwrap_or(file_duration.as_secs_f64());
+        let to_bytes = |bitrate: u64| (bitrate as f64 / 8.0 * seconds) as u64;
+        match self.bit_rate.as_deref().and_then(|b| b.parse().ok()) {
+            Some(bitrate) => (to_bytes(bitrate), SizeSource::Bitrate),
+            None => (to_bytes(self.typical_bitrate()), SizeSource::Heuristic),
+        }
+    }
+
+    /// Rough bit/s of a common encode in this format, erring on the low side
+    fn typical_bitrate(&self) -> u64 {
+        let channels = self
+            .channels
+            .as_deref()
+            .and_then(|c| c.parse::<u64>().ok())
+            .unwrap_or(2);
+        let per_channel = match self.format.as_deref() {
+            Some("PCM") => 1_152_000, // 48 kHz, 24 bit
+            Some("MLP FBA" | "TrueHD") => 500_000,
+            Some("FLAC" | "ALAC") => 400_000,
+            Some("DTS") if self.is_lossless() => 500_000,
+            Some("DTS") => 250_000,
+            Some("E-AC-3") => 110_000,
+            Some("AC-3") => 100_000,
+            Some("AAC" | "MPEG Audio" | "Vorbis") => 64_000,
+            Some("Opus") => 48_000,
+            // Subtitles don't have channels
+            Some("PGS") => return 30_000,
+            Some("VobSub" | "DVB Subtitle") => return 15_000,
+            _ if self.type_ == "Text" => return 100,
+            _ => 64_000,
+        };
+        per_channel * channels
+    }
+
+    fn is_lossless(&self) -> bool {
+        let features = self.format_additional_features.as_deref().unwrap_or("");
+        match self.format.as_deref() {
+            // TrueHD is MLP FBA to mediainfo
+            Some("MLP FBA" | "TrueHD" | "FLAC" | "PCM" | "ALAC") => true,
+            // XLL is the lossless extension of DTS-HD MA and DTS:X
+            Some("DTS") => features.contains("XLL"),
+            _ => false,
+        }
+    }
+
+    fn is_object_audio(&self) -> bool {
+        let commercial = self.format_commercial.as_deref().unwrap_or("");
+        let features = self.format_additional_features.as_deref().unwrap_or("");
+        commercial.contains("Atmos") || commercial.contains("DTS:X") || features.contains("JOC")
+    }
+}
+
+/// Many muxers only mark SDH subtitles in the track title
+fn is_sdh_title(title: &str) -> bool {
+    let title = title.to_ascii_lowercase();
+    title.contains("sdh") || title.contains("hearing impaired") || title.contains("(cc)")
 }
 
 /// Runs mediainfo on the file; no cache involved
@@ -92,14 +222,15 @@ pub fn probe_mediainfo(
         .find(|t| t.type_ == "Video")
         .with_context(|| format!("missing Video track in mediainfo output for {p:?}"))?;
 
+    let duration = Duration::from_secs_f64(
+        general_track
+            .duration
+            .as_ref()
+            .with_context(|| format!("missing Duration in General track for {p:?}"))?
+            .parse::<f64>()?,
+    );
     let info = MediaInfo {
-        duration: Duration::from_secs_f64(
-            general_track
-                .duration
-                .as_ref()
-                .with_context(|| format!("missing Duration in General track for {p:?}"))?
-                .parse::<f64>()?,
-        ),
+        duration,
         size: general_track
             .file_size
             .as_ref()
@@ -136,15 +267,86 @@ pub fn probe_mediainfo(
         audio_language: tracks
             .iter()
             .filter(|t| t.type_ == "Audio")
-            .filter_map(Track::to_lang_track)
+            .map(|t| t.to_lang_track(duration))
             .collect::<Vec<_>>(),
         subtitle_languages: tracks
             .iter()
             .filter(|t| t.type_ == "Text")
-            .filter_map(Track::to_lang_track)
+            .map(|t| t.to_lang_track(duration))
             .collect::<Vec<_>>(),
         whitelisted: false,
+        container: None,
+        disc: None,
     };
 
     Ok(info)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn track(fields: serde_json::Value) -> Track {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    #[test]
+    fn size_from_stream_size_first() {
+        let audio = track(json!({
+            "@type": "Audio", "StreamSize": "1234", "BitRate": "640000", "Duration": "10.0"
+        }));
+        assert_eq!(audio.size(HOUR), (1234, SizeSource::StreamSize));
+    }
+
+    #[test]
+    fn size_from_bitrate_times_duration() {
+        let audio = track(json!({"@type": "Audio", "BitRate": "640000", "Duration": "10.5"}));
+        assert_eq!(audio.size(HOUR), (840_000, SizeSource::Bitrate));
+        // Unparseable sizes fall through to the next source
+        let audio = track(json!({"@type": "Audio", "StreamSize": "", "BitRate": "8000"}));
+        assert_eq!(audio.size(HOUR), (3_600_000, SizeSource::Bitrate));
+    }
+
+    #[test]
+    fn size_falls_back_to_the_file_duration() {
+        let audio = track(json!({"@type": "Audio", "BitRate": "16000"}));
+        assert_eq!(
+            audio.size(Duration::from_secs(60)),
+            (120_000, SizeSource::Bitrate)
+        );
+    }
+
+    #[test]
+    fn size_from_a_typical_bitrate() {
+        let ac3 = track(json!({"@type": "Audio", "Format": "AC-3", "Channels": "6"}));
+        assert_eq!(ac3.typical_bitrate(), 600_000);
+        assert_eq!(
+            ac3.size(Duration::from_secs(80)),
+            (6_000_000, SizeSource::Heuristic)
+        );
+
+        // Stereo unless told otherwise
+        let aac = track(json!({"@type": "Audio", "Format": "AAC"}));
+        assert_eq!(aac.typical_bitrate(), 128_000);
+        let dts_ma = track(json!({
+            "@type": "Audio", "Format": "DTS", "Format_AdditionalFeatures": "XLL", "Channels": "8"
+        }));
+        assert_eq!(dts_ma.typical_bitrate(), 4_000_000);
+        let dts = track(json!({"@type": "Audio", "Format": "DTS", "Channels": "6"}));
+        assert_eq!(dts.typical_bitrate(), 1_500_000);
+    }
+
+    #[test]
+    fn subtitle_bitrates_ignore_channels() {
+        let pgs = track(json!({"@type": "Text", "Format": "PGS", "Channels": "6"}));
+        assert_eq!(pgs.typical_bitrate(), 30_000);
+        let vobsub = track(json!({"@type": "Text", "Format": "VobSub"}));
+        assert_eq!(vobsub.typical_bitrate(), 15_000);
+        let srt = track(json!({"@type": "Text", "Format": "UTF-8"}));
+        assert_eq!(srt.typical_bitrate(), 100);
+        assert_eq!(srt.size(HOUR), (45_000, SizeSource::Heuristic));
+    }
+}
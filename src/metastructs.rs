@@ -3,6 +3,11 @@ use std::time::Duration;
 
 use time::OffsetDateTime;
 
+use crate::disc::DiscInfo;
+use crate::language;
+use crate::sniff::Container;
+use serde::{Deserialize, Serialize};
+
 #[allow(unused)]
 #[derive(Debug, Clone)]
 pub struct MediaInfo {
@@ -17,13 +22,118 @@ pub struct MediaInfo {
     pub audio_language: Vec<LangTrack>,
     pub subtitle_languages: Vec<LangTrack>,
     pub whitelisted: bool,
+    /// Sniffed from the file's first bytes with `--sniff`, never cached
+    pub container: Option<Container>,
+    /// Set for Blu-ray and DVD folders, which are probed as one title
+    pub disc: Option<DiscInfo>,
 }
 
-#[derive(Debug, Clone)]
+/// Image-based subtitle formats as mediainfo names them: large, unstyleable, and many
+/// clients transcode the whole video to burn them in
+const IMAGE_SUBTITLE_FORMATS: &[&str] = &["PGS", "VobSub", "DVB Subtitle"];
+
+/// How a track's size was determined, from most to least reliable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizeSource {
+    /// mediainfo's StreamSize, measured by the muxer or mediainfo
+    #[default]
+    StreamSize,
+    /// The track's bitrate times its duration
+    Bitrate,
+    /// A typical bitrate for the codec and channel count times the duration
+    Heuristic,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LangTrack {
     pub language: String,
-    /// Stream size in bytes, 0 if the container carries no per-track statistics
+    /// Stream size in bytes, estimated if the container carries no per-track statistics
     pub size: u64,
+    /// Where `size` comes from
+    #[serde(default)]
+    pub size_source: SizeSource,
+    /// mediainfo's Format, e.g. "UTF-8" (SRT), "ASS", "PGS" or "AAC"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Flagged as the default track of its kind
+    #[serde(default)]
+    pub default: bool,
+    /// Subtitles only shown for foreign-language parts, signs and the like
+    #[serde(default)]
+    pub forced: bool,
+    /// Subtitles for the deaf and hard of hearing, by flag or title
+    #[serde(default)]
+    pub sdh: bool,
+    /// Audio channel count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<u32>,
+    /// e.g. "L R C LFE Ls Rs"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_layout: Option<String>,
+    /// bit/s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    /// TrueHD, DTS-HD MA, FLAC, PCM and the like
+    #[serde(default)]
+    pub lossless: bool,
+    /// Dolby Atmos or DTS:X
+    #[serde(default)]
+    pub object_audio: bool,
+    /// Commentary, by flag or title
+    #[serde(default)]
+    pub commentary: bool,
+    /// For untagged subtitles: language guessed from their text, `und` if that was inconclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected: Option<String>,
+    /// A sidecar file next to the video rather than a track inside it. Never cached,
+    /// sidecars are looked up again on every run.
+    #[serde(skip)]
+    pub external: bool,
+    /// Index of the track among all streams of the file, as ffmpeg and mkvpropedit count
+    /// them. `None` for captions inside the video stream, and for sidecars.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<u32>,
+}
+
+impl LangTrack {
+    /// The tagged language, or the confident guess for an untagged track
+    pub fn effective_language(&self) -> &str {
+        match &self.detected {
+            Some(detected) if language::is_unknown(&self.language) => detected,
+            _ => &self.language,
+        }
+    }
+
+    /// Untagged text subtitles not looked at yet, which can be read on their own
+    pub fn needs_detection(&self) -> bool {
+        language::is_unknown(&self.language)
+            && self.detected.is_none()
+            && !self.is_image_based()
+            && (self.external || self.stream.is_some())
+    }
+
+    pub fn is_size_estimated(&self) -> bool {
+        self.size_source != SizeSource::StreamSize
+    }
+
+    /// Codec as people call it, mediainfo's format names are not always that
+    pub fn codec_name(&self) -> &str {
+        match self.format.as_deref() {
+            Some("MLP FBA") => "TrueHD",
+            Some("DTS") if self.lossless => "DTS-HD MA",
+            Some(format) => format,
+            None => "unknown codec",
+        }
+    }
+
+    pub fn is_image_based(&self) -> bool {
+        self.format
+            .as_deref()
+            .is_some_and(|f| IMAGE_SUBTITLE_FORMATS.contains(&f))
+    }
 }
 
 impl MediaInfo {
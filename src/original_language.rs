@@ -0,0 +1,102 @@
+use crate::language;
+use crate::metastructs::LangTrack;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Tags we read the original language from, Kodi/tinyMediaManager style
+const NFO_TAGS: &[&str] = &["originallanguage", "original_language"];
+/// Title-wide nfo files, checked after `<video stem>.nfo`
+const NFO_FILE_NAMES: &[&str] = &["movie.nfo", "tvshow.nfo"];
+
+/// Works out the language a title was originally made in, first match wins:
+/// 1. `layer`, the `original_language` of the innermost `.jwatch.toml` setting one
+/// 2. `original_languages` from the config, longest directory prefix relative to `root`
+/// 3. a sidecar `.nfo`: `<stem>.nfo`, then `movie.nfo`/`tvshow.nfo` up to `root`
+/// 4. the default audio track, otherwise the first one, ignoring untagged tracks
+pub fn resolve(
+    file: &Path,
+    root: &Path,
+    layer: Option<&str>,
+    overrides: &BTreeMap<PathBuf, String>,
+    audio: &[LangTrack],
+) -> Option<String> {
+    if let Some(lang) = layer {
+        return Some(lang.to_owned());
+    }
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    let overridden = overrides
+        .iter()
+        .filter(|(dir, _)| relative.starts_with(dir))
+        .max_by_key(|(dir, _)| dir.components().count());
+    if let Some((_, lang)) = overridden {
+        return Some(lang.clone());
+    }
+
+    if let Some(lang) = from_nfo(file, root) {
+        return Some(lang);
+    }
+
+    let mut tagged = audio.iter().filter(|t| !language::is_unknown(&t.language));
+    tagged
+        .clone()
+        .find(|t| t.default)
+        .or_else(|| tagged.next())
+        .map(|t| t.language.clone())
+}
+
+fn from_nfo(file: &Path, root: &Path) -> Option<String> {
+    let own = file.with_extension("nfo");
+    let title_wide = file
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .flat_map(|dir| NFO_FILE_NAMES.iter().map(move |name| dir.join(name)));
+    std::iter::once(own)
+        .chain(title_wide)
+        .filter_map(|nfo| fs::read_to_string(nfo).ok())
+        .find_map(|xml| nfo_tag(&xml))
+}
+
+fn nfo_tag(xml: &str) -> Option<String> {
+    NFO_TAGS.iter().find_map(|tag| {
+        let start = xml.find(&format!("<{tag}>"))? + tag.len() + 2;
+        let len = xml[start..].find(&format!("</{tag}>"))?;
+        let lang = xml[start..start + len].trim();
+        (!lang.is_empty()).then(|| language::normalize(lang))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nfo_tag_names() {
+        let kodi = "<movie>\n  <title>Spirited Away</title>\n  <originallanguage>ja</originallanguage>\n</movie>";
+        assert_eq!(nfo_tag(kodi).as_deref(), Some("ja"));
+        let tmm = "<tvshow><original_language>ko</original_language></tvshow>";
+        assert_eq!(nfo_tag(tmm).as_deref(), Some("ko"));
+    }
+
+    #[test]
+    fn nfo_tag_normalizes_the_code() {
+        let nfo = "<movie><originallanguage>\n    jpn\n  </originallanguage></movie>";
+        assert_eq!(nfo_tag(nfo).as_deref(), Some("ja"));
+        let nfo = "<movie><originallanguage>French</originallanguage></movie>";
+        assert_eq!(nfo_tag(nfo).as_deref(), Some("fr"));
+    }
+
+    #[test]
+    fn nfo_tag_empty_or_broken() {
+        assert_eq!(
+            nfo_tag("<movie><originallanguage>  </originallanguage></movie>"),
+            None
+        );
+        assert_eq!(nfo_tag("<movie><originallanguage>ja</movie>"), None);
+        assert_eq!(nfo_tag("<movie><title>Ran</title></movie>"), None);
+        // An empty tag doesn't hide the other name
+        let both = "<originallanguage></originallanguage><original_language>de</original_language>";
+        assert_eq!(nfo_tag(both).as_deref(), Some("de"));
+    }
+}
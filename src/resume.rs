@@ -0,0 +1,21 @@
+use crate::walk::PendingDir;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where an interrupted scan stopped. Kept in the cache database, so `scan --resume`
+/// continues from there instead of walking and stat'ing everything again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanState {
+    /// Resuming only makes sense for the same folders
+    pub roots: Vec<PathBuf>,
+    /// Probed already, served from the cache without a stat
+    pub done: Vec<PathBuf>,
+    /// Found but not probed yet, in the order they were going to be
+    pub files: Vec<PathBuf>,
+    /// Not listed yet
+    pub dirs: Vec<PendingDir>,
+    /// Found so far, the rest turn up while the walk continues
+    pub sidecar_files: Vec<PathBuf>,
+    /// Folders with a `.jwatch.toml`, read again on resume
+    pub layer_dirs: Vec<PathBuf>,
+}
@@ -0,0 +1,801 @@
+use crate::language;
+use crate::metastructs::{LangTrack, MediaInfo};
+use indicatif::HumanBytes;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::Duration;
+
+const ACCEPTED_BITRATE_RANGE: std::ops::Range<f64> = 0.2..20.0;
+const ACCEPTED_LANGS: &[&str] = &["en", "de"];
+/// bit/s, transparent for most material
+const OPUS_BITRATE_PER_CHANNEL: u64 = 64_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Rule {
+    Bitrate,
+    AudioLanguage,
+    SubtitleLanguage,
+    MissingAudio,
+    MissingSubtitles,
+    UntaggedTracks,
+    ImageSubtitles,
+    MissingSdh,
+    LosslessAudio,
+    MissingStereo,
+    Commentary,
+    ExtensionMismatch,
+    DiscRemux,
+}
+
+impl Rule {
+    pub const ALL: &[Rule] = &[
+        Rule::Bitrate,
+        Rule::AudioLanguage,
+        Rule::SubtitleLanguage,
+        Rule::MissingAudio,
+        Rule::MissingSubtitles,
+        Rule::UntaggedTracks,
+        Rule::ImageSubtitles,
+        Rule::MissingSdh,
+        Rule::LosslessAudio,
+        Rule::MissingStereo,
+        Rule::Commentary,
+        Rule::ExtensionMismatch,
+        Rule::DiscRemux,
+    ];
+
+    /// Stable identifier, used in the cache and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::Bitrate => "bitrate",
+            Rule::AudioLanguage => "audio-language",
+            Rule::SubtitleLanguage => "subtitle-language",
+            Rule::MissingAudio => "missing-audio",
+            Rule::MissingSubtitles => "missing-subtitles",
+            Rule::UntaggedTracks => "untagged-tracks",
+            Rule::ImageSubtitles => "image-subtitles",
+            Rule::MissingSdh => "missing-sdh",
+            Rule::LosslessAudio => "lossless-audio",
+            Rule::MissingStereo => "missing-stereo",
+            Rule::Commentary => "commentary",
+            Rule::ExtensionMismatch => "extension-mismatch",
+            Rule::DiscRemux => "disc-remux",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.iter().copied().find(|r| r.name() == name)
+    }
+
+    /// What a file violating this rule "has", as in "newly has undesired audio"
+    pub fn description(self) -> &'static str {
+        match self {
+            Rule::Bitrate => "bitrate",
+            Rule::AudioLanguage => "undesired audio",
+            Rule::SubtitleLanguage => "undesired subtitles",
+            Rule::MissingAudio => "missing required audio",
+            Rule::MissingSubtitles => "missing required subtitles",
+            Rule::UntaggedTracks => "untagged tracks",
+            Rule::ImageSubtitles => "only image-based subtitles",
+            Rule::MissingSdh => "missing SDH subtitles",
+            Rule::LosslessAudio => "oversized lossless audio",
+            Rule::MissingStereo => "no stereo-compatible audio",
+            Rule::Commentary => "commentary tracks",
+            Rule::ExtensionMismatch => "an extension not matching its container",
+            Rule::DiscRemux => "a disc folder that could be remuxed",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+/// Thresholds the rules judge against, built-in defaults overridden by the config
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// mbit/s
+    pub accepted_bitrate: std::ops::Range<f64>,
+    pub accepted_langs: Vec<String>,
+    /// At least one audio track must be in one of these, if any are given
+    pub required_audio_langs: Vec<String>,
+    /// Like `required_audio_langs`, for subtitle tracks
+    pub required_subtitle_langs: Vec<String>,
+    /// What to do about tracks without a (meaningful) language tag
+    pub unknown_tracks: UnknownTracks,
+    /// Forced subtitles are never undesired, whatever their language
+    pub keep_forced_subs: bool,
+    /// Each of these languages needs an SDH subtitle track
+    pub required_sdh_langs: Vec<String>,
+    /// Lossless audio tracks above this many bytes are worth converting
+    pub max_lossless_audio_size: Option<u64>,
+    /// At least one audio track must have at most two channels
+    pub require_stereo: bool,
+    /// Commentary tracks count toward savings
+    pub remove_commentary: bool,
+    /// Rules that report nothing
+    pub disabled_rules: Vec<Rule>,
+    /// `.jwatch.toml` layer that last changed the thresholds of a rule
+    pub set_by: BTreeMap<Rule, String>,
+}
+
+/// Handling of tracks whose language is [unknown](language::is_unknown)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownTracks {
+    /// Treated like accepted tracks
+    Tolerate,
+    /// Kept, but reported so they get tagged
+    #[default]
+    Flag,
+    /// Treated like undesired tracks, counting toward savings
+    Remove,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            accepted_bitrate: ACCEPTED_BITRATE_RANGE,
+            accepted_langs: ACCEPTED_LANGS.iter().map(|&l| l.to_owned()).collect(),
+            required_audio_langs: vec![],
+            required_subtitle_langs: vec![],
+            unknown_tracks: UnknownTracks::default(),
+            keep_forced_subs: true,
+            required_sdh_langs: vec![],
+            max_lossless_audio_size: None,
+            require_stereo: false,
+            remove_commentary: false,
+            disabled_rules: vec![],
+            set_by: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavingsKind {
+    Video,
+    Audio,
+    Subtitles,
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub rule: Rule,
+    pub reason: String,
+    /// Bytes that fixing this finding would at least free up
+    pub saved: u64,
+    /// The part of `saved` that rests on estimated track sizes
+    pub estimated: u64,
+    pub saved_kind: SavingsKind,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Savings {
+    pub video: u64,
+    pub audio: u64,
+    pub subtitles: u64,
+    /// The part of each figure above that rests on estimated track sizes
+    pub video_estimated: u64,
+    pub audio_estimated: u64,
+    pub subtitles_estimated: u64,
+}
+
+impl Savings {
+    pub fn add(&mut self, finding: &Finding) {
+        let (saved, estimated) = match finding.saved_kind {
+            SavingsKind::Video => (&mut self.video, &mut self.video_estimated),
+            SavingsKind::Audio => (&mut self.audio, &mut self.audio_estimated),
+            SavingsKind::Subtitles => (&mut self.subtitles, &mut self.subtitles_estimated),
+        };
+        *saved += finding.saved;
+        *estimated += finding.estimated;
+    }
+
+    pub fn merge(&mut self, other: &Savings) {
+        self.video += other.video;
+        self.audio += other.audio;
+        self.subtitles += other.subtitles;
+        self.video_estimated += other.video_estimated;
+        self.audio_estimated += other.audio_estimated;
+        self.subtitles_estimated += other.subtitles_estimated;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.video + self.audio + self.subtitles
+    }
+
+    pub fn total_estimated(&self) -> u64 {
+        self.video_estimated + self.audio_estimated + self.subtitles_estimated
+    }
+}
+
+fn estimated_size(tracks: &[&LangTrack]) -> u64 {
+    tracks
+        .iter()
+        .filter(|t| t.is_size_estimated())
+        .map(|t| t.size)
+        .sum()
+}
+
+/// Size of `track` re-encoded to Opus at [`OPUS_BITRATE_PER_CHANNEL`], assuming stereo when the channel count is unknown
+fn opus_size(track: &LangTrack, duration: Duration) -> u64 {
+    let bitrate = OPUS_BITRATE_PER_CHANNEL * u64::from(track.channels.unwrap_or(2));
+    (bitrate as f64 / 8.0 * duration.as_secs_f64()) as u64
+}
+
+/// e.g. "en TrueHD 8ch Atmos"
+fn describe_audio(track: &LangTrack) -> String {
+    let mut description = format!("{} {}", track.language, track.codec_name());
+    if let Some(channels) = track.channels {
+        description += &format!(" {channels}ch");
+    }
+    if track.object_audio {
+        description += match track.format.as_deref() {
+            Some("DTS") => " DTS:X",
+            _ => " Atmos",
+        };
+    }
+    description
+}
+
+/// Runs every rule against one file. Audio in its `original_language` is always accepted.
+pub fn evaluate(
+    mediainfo: &MediaInfo,
+    policy: &Policy,
+    original_language: Option<&str>,
+) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    if !policy.accepted_bitrate.contains(&mediainfo.megabitrate()) {
+        let reason = format!(
+            "Undesired bitrate: {:<4.1} mbit/s with codec {:<4}",
+            mediainfo.megabitrate(),
+            mediainfo.codec,
+        );
+        let mut saved = 0;
+        if mediainfo.megabitrate() >= policy.accepted_bitrate.end {
+            let max_bytes_per_sec = policy.accepted_bitrate.end * 2.0_f64.powi(20) / 8.0;
+            let bytes_per_sec = mediainfo.bitrate as f64 / 8.0;
+            saved = ((bytes_per_sec - max_bytes_per_sec) * mediainfo.duration.as_secs_f64()) as u64;
+        }
+        findings.push(Finding {
+            rule: Rule::Bitrate,
+            reason,
+            saved,
+            estimated: 0,
+            saved_kind: SavingsKind::Video,
+        });
+    }
+
+    if let Some(disc) = &mediainfo.disc {
+        // Menus, extras and other angles go, the main feature's streams stay
+        findings.push(Finding {
+            rule: Rule::DiscRemux,
+            reason: format!(
+                "{} folder, remux main feature {} ({} of {}) to MKV",
+                disc.kind,
+                disc.main_feature,
+                HumanBytes(disc.main_feature_size),
+                HumanBytes(mediainfo.size as u64),
+            ),
+            saved: (mediainfo.size as u64).saturating_sub(disc.main_feature_size),
+            estimated: 0,
+            saved_kind: SavingsKind::Video,
+        });
+    }
+
+    let desired_langs = &policy.accepted_langs;
+    let desired_audio = desired_langs
+        .iter()
+        .cloned()
+        .chain(original_language.map(str::to_owned))
+        .collect::<Vec<_>>();
+    // Guessed subtitle languages count like tagged ones
+    let removable = |t: &LangTrack, accepted: &[String]| {
+        let lang = t.effective_language();
+        if language::is_unknown(lang) {
+            policy.unknown_tracks == UnknownTracks::Remove
+        } else {
+            !accepted.iter().any(|a| a == lang)
+        }
+    };
+    let kept = kept_audio_track(&mediainfo.audio_language, |t| removable(t, &desired_audio));
+    let undesired = mediainfo
+        .audio_language
+        .iter()
+        .enumerate()
+        .filter(|&(i, t)| removable(t, &desired_audio) && Some(i) != kept)
+        .map(|(_, t)| t)
+        .collect::<Vec<_>>();
+    if !undesired.is_empty() {
+        let langs = undesired
+            .iter()
+            .map(|t| t.language.as_str())
+            .collect::<Vec<_>>();
+        let mut reason = format!("Undesired languages {}", langs.join(" "));
+        if let Some(kept) = kept {
+            let kept = &mediainfo.audio_language[kept].language;
+            reason += &format!(" (keeping {kept}, no accepted audio)");
+        }
+        findings.push(Finding {
+            rule: Rule::AudioLanguage,
+            reason,
+            saved: undesired.iter().map(|t| t.size).sum(),
+            estimated: estimated_size(&undesired),
+            saved_kind: SavingsKind::Audio,
+        });
+    }
+
+    // Tracks already counted above are not counted again
+    let remaining_audio = mediainfo
+        .audio_language
+        .iter()
+        .filter(|t| !undesired.iter().any(|u| std::ptr::eq(*u, *t)))
+        .collect::<Vec<_>>();
+    let mut commentary = remaining_audio
+        .iter()
+        .filter(|t| policy.remove_commentary && t.commentary)
+        .copied()
+        .collect::<Vec<_>>();
+    // Like undesired languages, commentary never leaves a file silent: of the audio left,
+    // the default track stays, otherwise the first
+    if !commentary.is_empty() && commentary.len() == remaining_audio.len() {
+        commentary.remove(commentary.iter().position(|t| t.default).unwrap_or(0));
+    }
+    if !commentary.is_empty() {
+        let titles = commentary
+            .iter()
+            .map(|t| t.title.as_deref().unwrap_or(&t.language))
+            .collect::<Vec<_>>();
+        findings.push(Finding {
+            rule: Rule::Commentary,
+            reason: format!("Commentary tracks: {}", titles.join(", ")),
+            saved: commentary.iter().map(|t| t.size).sum(),
+            estimated: estimated_size(&commentary),
+            saved_kind: SavingsKind::Audio,
+        });
+    }
+
+    if let Some(max_size) = policy.max_lossless_audio_size {
+        let oversized = remaining_audio
+            .iter()
+            .filter(|t| t.lossless && t.size > max_size)
+            .filter(|t| !commentary.iter().any(|c| std::ptr::eq(*c, **t)))
+            .copied()
+            .collect::<Vec<_>>();
+        if !oversized.is_empty() {
+            let saved_by = |t: &&LangTrack| t.size.saturating_sub(opus_size(t, mediainfo.duration));
+            let tracks = oversized
+                .iter()
+                .map(|t| describe_audio(t))
+                .collect::<Vec<_>>();
+            findings.push(Finding {
+                rule: Rule::LosslessAudio,
+                reason: format!("Lossless audio over the size limit: {}", tracks.join(", ")),
+                saved: oversized.iter().map(saved_by).sum(),
+                estimated: oversized
+                    .iter()
+                    .filter(|t| t.is_size_estimated())
+                    .map(saved_by)
+                    .sum(),
+                saved_kind: SavingsKind::Audio,
+            });
+        }
+    }
+
+    let audio = &mediainfo.audio_language;
+    if policy.require_stereo
+        && !audio.is_empty()
+        && !audio.iter().any(|t| t.channels.is_some_and(|c| c <= 2))
+    {
+        let tracks = audio.iter().map(describe_audio).collect::<Vec<_>>();
+        findings.push(Finding {
+            rule: Rule::MissingStereo,
+            reason: format!("No stereo-compatible audio: {}", tracks.join(", ")),
+            saved: 0,
+            estimated: 0,
+            saved_kind: SavingsKind::Audio,
+        });
+    }
+
+    let undesired_subs = mediainfo
+        .subtitle_languages
+        .iter()
+        .filter(|t| !(t.forced && policy.keep_forced_subs) && removable(t, desired_langs))
+        .collect::<Vec<_>>();
+    if !undesired_subs.is_empty() {
+        let langs = undesired_subs
+            .iter()
+            .map(|t| {
+                let mut lang = match t.language == t.effective_language() {
+                    true => t.language.clone(),
+                    false => format!("{} (detected)", t.effective_language()),
+                };
+                if t.external {
+                    lang += " (external)";
+                }
+                lang
+            })
+            .collect::<Vec<_>>();
+        findings.push(Finding {
+            rule: Rule::SubtitleLanguage,
+            reason: format!("Undesired subtitle languages {}", langs.join(" ")),
+            saved: undesired_subs.iter().map(|t| t.size).sum(),
+            estimated: estimated_size(&undesired_subs),
+            saved_kind: SavingsKind::Subtitles,
+        });
+    }
+
+    if policy.unknown_tracks == UnknownTracks::Flag {
+        let untagged = |tracks: &[LangTrack]| {
+            tracks
+                .iter()
+                .filter(|t| language::is_unknown(&t.language))
+                .count()
+        };
+        let audio = untagged(&mediainfo.audio_language);
+        let subs = untagged(&mediainfo.subtitle_languages);
+        if audio + subs > 0 {
+            findings.push(Finding {
+                rule: Rule::UntaggedTracks,
+                reason: format!("Untagged tracks: {audio} audio, {subs} subtitle"),
+                saved: 0,
+                estimated: 0,
+                saved_kind: SavingsKind::Audio,
+            });
+        }
+    }
+
+    let subs = &mediainfo.subtitle_languages;
+    if !subs.is_empty() && subs.iter().all(LangTrack::is_image_based) {
+        let mut formats = subs
+            .iter()
+            .filter_map(|t| t.format.as_deref())
+            .collect::<Vec<_>>();
+        formats.sort_unstable();
+        formats.dedup();
+        findings.push(Finding {
+            rule: Rule::ImageSubtitles,
+            reason: format!("Only image-based subtitles: {}", formats.join(" ")),
+            saved: 0,
+            estimated: 0,
+            saved_kind: SavingsKind::Subtitles,
+        });
+    }
+
+    let missing_sdh = policy
+        .required_sdh_langs
+        .iter()
+        .filter(|lang| {
+            !subs
+                .iter()
+                .any(|t| t.sdh && t.effective_language() == *lang)
+        })
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !missing_sdh.is_empty() {
+        findings.push(Finding {
+            rule: Rule::MissingSdh,
+            reason: format!("Missing SDH subtitles: {}", missing_sdh.join(" ")),
+            saved: 0,
+            estimated: 0,
+            saved_kind: SavingsKind::Subtitles,
+        });
+    }
+
+    if let Some(finding) = missing_required(
+        &mediainfo.audio_language,
+        &policy.required_audio_langs,
+        Rule::MissingAudio,
+        SavingsKind::Audio,
+    ) {
+        findings.push(finding);
+    }
+    if let Some(finding) = missing_required(
+        &mediainfo.subtitle_languages,
+        &policy.required_subtitle_langs,
+        Rule::MissingSubtitles,
+        SavingsKind::Subtitles,
+    ) {
+        findings.push(finding);
+    }
+
+    findings
+}
+
+/// Index of the audio track kept despite being removable, because removing them all
+/// would leave the file silent: the default track, otherwise the first.
+/// A known original-language track is already accepted, so never removable.
+/// `None` if a track stays anyway or there is no audio at all.
+fn kept_audio_track(tracks: &[LangTrack], removable: impl Fn(&LangTrack) -> bool) -> Option<usize> {
+    if tracks.is_empty() || tracks.iter().any(|t| !removable(t)) {
+        return None;
+    }
+    Some(tracks.iter().position(|t| t.default).unwrap_or(0))
+}
+
+/// Only knows the container with `--sniff`
+pub fn extension_mismatch(file: &Path, mediainfo: &MediaInfo) -> Option<Finding> {
+    let container = mediainfo.container?;
+    if container.matches_extension(file) {
+        return None;
+    }
+    Some(Finding {
+        rule: Rule::ExtensionMismatch,
+        reason: format!(
+            "Extension doesn't match the {container} container (expected {})",
+            container.extensions().join(", ")
+        ),
+        saved: 0,
+        estimated: 0,
+        saved_kind: SavingsKind::Video,
+    })
+}
+
+/// `None` if no languages are required or any track matches one of them
+fn missing_required(
+    tracks: &[LangTrack],
+    required: &[String],
+    rule: Rule,
+    kind: SavingsKind,
+) -> Option<Finding> {
+    if required.is_empty()
+        || tracks
+            .iter()
+            .any(|t| required.iter().any(|r| r == t.effective_language()))
+    {
+        return None;
+    }
+    let what = match kind {
+        SavingsKind::Subtitles => "subtitles",
+        _ => "audio",
+    };
+    Some(Finding {
+        rule,
+        reason: format!("Missing required {what}: {}", required.join(" ")),
+        saved: 0,
+        estimated: 0,
+        saved_kind: kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metastructs::Codec;
+    use time::OffsetDateTime;
+
+    const MB: u64 = 1 << 20;
+
+    fn track(language: &str, size: u64) -> LangTrack {
+        LangTrack {
+            language: language.to_owned(),
+            size,
+            ..LangTrack::default()
+        }
+    }
+
+    fn commentary(language: &str, title: &str, size: u64) -> LangTrack {
+        LangTrack {
+            title: Some(title.to_owned()),
+            commentary: true,
+            ..track(language, size)
+        }
+    }
+
+    /// An hour of 5 mbit/s video, within the default bitrate range
+    fn media(audio: Vec<LangTrack>, subtitles: Vec<LangTrack>) -> MediaInfo {
+        MediaInfo {
+            duration: Duration::from_secs(3600),
+            size: 2_250 * MB as usize,
+            bitrate: 5 * MB as usize,
+            height: 1080,
+            width: 1920,
+            codec: Codec::from_str("HEVC"),
+            last_checked: OffsetDateTime::UNIX_EPOCH,
+            mtime: 0,
+            audio_language: audio,
+            subtitle_languages: subtitles,
+            whitelisted: false,
+            container: None,
+            disc: None,
+        }
+    }
+
+    fn find(findings: &[Finding], rule: Rule) -> Option<&Finding> {
+        findings.iter().find(|f| f.rule == rule)
+    }
+
+    fn default_track(language: &str, size: u64) -> LangTrack {
+        LangTrack {
+            default: true,
+            ..track(language, size)
+        }
+    }
+
+    #[test]
+    fn all_undesired_audio_keeps_the_default_track() {
+        let file = media(
+            vec![
+                track("fr", 100 * MB),
+                default_track("es", 200 * MB),
+                track("it", 300 * MB),
+            ],
+            vec![],
+        );
+        let findings = evaluate(&file, &Policy::default(), None);
+        let finding = find(&findings, Rule::AudioLanguage).unwrap();
+        assert_eq!(
+            finding.reason,
+            "Undesired languages fr it (keeping es, no accepted audio)"
+        );
+        assert_eq!(finding.saved, 400 * MB);
+    }
+
+    #[test]
+    fn all_undesired_audio_without_a_default_keeps_the_first() {
+        let file = media(vec![track("fr", 100 * MB), track("es", 200 * MB)], vec![]);
+        let findings = evaluate(&file, &Policy::default(), None);
+        let finding = find(&findings, Rule::AudioLanguage).unwrap();
+        assert_eq!(
+            finding.reason,
+            "Undesired languages es (keeping fr, no accepted audio)"
+        );
+        assert_eq!(finding.saved, 200 * MB);
+
+        let single = media(vec![track("fr", 100 * MB)], vec![]);
+        assert!(
+            find(
+                &evaluate(&single, &Policy::default(), None),
+                Rule::AudioLanguage
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn accepted_audio_needs_no_kept_track() {
+        let file = media(vec![track("fr", 100 * MB), track("en", 200 * MB)], vec![]);
+        let findings = evaluate(&file, &Policy::default(), None);
+        let finding = find(&findings, Rule::AudioLanguage).unwrap();
+        assert_eq!(finding.reason, "Undesired languages fr");
+        assert_eq!(finding.saved, 100 * MB);
+    }
+
+    #[test]
+    fn original_language_audio_is_accepted() {
+        let file = media(vec![track("ja", 100 * MB), track("fr", 200 * MB)], vec![]);
+        let findings = evaluate(&file, &Policy::default(), Some("ja"));
+        let finding = find(&findings, Rule::AudioLanguage).unwrap();
+        assert_eq!(finding.reason, "Undesired languages fr");
+        assert_eq!(finding.saved, 200 * MB);
+
+        // Only audio, subtitles in it are still undesired
+        let subtitles = media(vec![track("ja", 100 * MB)], vec![track("ja", MB)]);
+        let findings = evaluate(&subtitles, &Policy::default(), Some("ja"));
+        assert!(find(&findings, Rule::AudioLanguage).is_none());
+        assert!(find(&findings, Rule::SubtitleLanguage).is_some());
+    }
+
+    #[test]
+    fn unknown_track_policies() {
+        let file = media(
+            vec![track("en", 100 * MB), track("und", 200 * MB)],
+            vec![track("", MB)],
+        );
+        let evaluate_with = |unknown_tracks| {
+            let policy = Policy {
+                unknown_tracks,
+                ..Policy::default()
+            };
+            evaluate(&file, &policy, None)
+        };
+
+        let tolerated = evaluate_with(UnknownTracks::Tolerate);
+        assert!(tolerated.is_empty(), "{tolerated:?}");
+
+        let flagged = evaluate_with(UnknownTracks::Flag);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].reason, "Untagged tracks: 1 audio, 1 subtitle");
+
+        let removed = evaluate_with(UnknownTracks::Remove);
+        assert_eq!(find(&removed, Rule::AudioLanguage).unwrap().saved, 200 * MB);
+        assert_eq!(find(&removed, Rule::SubtitleLanguage).unwrap().saved, MB);
+        assert!(find(&removed, Rule::UntaggedTracks).is_none());
+
+        // Still never silent
+        let untagged = media(vec![track("und", 100 * MB)], vec![]);
+        let policy = Policy {
+            unknown_tracks: UnknownTracks::Remove,
+            ..Policy::default()
+        };
+        assert!(evaluate(&untagged, &policy, None).is_empty());
+    }
+
+    #[test]
+    fn image_subtitle_formats_are_listed_once() {
+        let subtitle = |language, format: &str| LangTrack {
+            format: Some(format.to_owned()),
+            ..track(language, MB)
+        };
+        let file = media(
+            vec![track("en", 100 * MB)],
+            vec![
+                subtitle("en", "PGS"),
+                subtitle("de", "VobSub"),
+                subtitle("en", "PGS"),
+            ],
+        );
+        let findings = evaluate(&file, &Policy::default(), None);
+        assert_eq!(
+            find(&findings, Rule::ImageSubtitles).unwrap().reason,
+            "Only image-based subtitles: PGS VobSub"
+        );
+    }
+
+    #[test]
+    fn commentary_never_leaves_a_file_silent() {
+        let policy = Policy {
+            remove_commentary: true,
+            ..Policy::default()
+        };
+
+        let only = media(
+            vec![commentary("en", "Director's Commentary", 100 * MB)],
+            vec![],
+        );
+        assert!(find(&evaluate(&only, &policy, None), Rule::Commentary).is_none());
+
+        // The kept undesired track is all that is left
+        let kept = media(
+            vec![
+                commentary("fr", "Commentaire", 100 * MB),
+                track("es", 200 * MB),
+            ],
+            vec![],
+        );
+        let findings = evaluate(&kept, &policy, None);
+        assert!(find(&findings, Rule::Commentary).is_none());
+        assert_eq!(
+            find(&findings, Rule::AudioLanguage).unwrap().saved,
+            200 * MB
+        );
+
+        let default = LangTrack {
+            default: true,
+            ..commentary("en", "Cast Commentary", 80 * MB)
+        };
+        let all = media(
+            vec![commentary("en", "Director's Commentary", 100 * MB), default],
+            vec![],
+        );
+        let finding = evaluate(&all, &policy, None);
+        let finding = find(&finding, Rule::Commentary).unwrap();
+        assert_eq!(finding.reason, "Commentary tracks: Director's Commentary");
+        assert_eq!(finding.saved, 100 * MB);
+    }
+
+    #[test]
+    fn commentary_next_to_the_main_audio_counts() {
+        let policy = Policy {
+            remove_commentary: true,
+            ..Policy::default()
+        };
+        let file = media(
+            vec![
+                track("en", 500 * MB),
+                commentary("en", "Commentary", 100 * MB),
+            ],
+            vec![],
+        );
+        let findings = evaluate(&file, &policy, None);
+        assert_eq!(find(&findings, Rule::Commentary).unwrap().saved, 100 * MB);
+
+        let kept = evaluate(&file, &Policy::default(), None);
+        assert!(find(&kept, Rule::Commentary).is_none());
+    }
+}
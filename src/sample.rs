@@ -0,0 +1,245 @@
+use crate::JwatchResult;
+use color_eyre::eyre::bail;
+use std::path::PathBuf;
+
+/// z-score of the two-sided 95% confidence intervals in sampled reports
+const Z_95: f64 = 1.96;
+
+/// `--sample`: how many of the walked files get probed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    Count(usize),
+    Percent(f64),
+}
+
+impl SampleSize {
+    /// `500` or `5%`
+    pub fn from_arg(arg: &str) -> JwatchResult<SampleSize> {
+        let size = match arg.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f64>() {
+                Ok(p) if p > 0.0 && p <= 100.0 => SampleSize::Percent(p),
+                _ => bail!("--sample {arg}: the percentage must be above 0 and at most 100"),
+            },
+            None => match arg.trim().parse::<usize>() {
+                Ok(n) if n > 0 => SampleSize::Count(n),
+                _ => {
+                    bail!("--sample {arg}: expected a file count like 500, or a percentage like 5%")
+                }
+            },
+        };
+        Ok(size)
+    }
+
+    fn of(self, population: usize) -> usize {
+        match self {
+            SampleSize::Count(n) => n.min(population),
+            SampleSize::Percent(p) => {
+                ((population as f64 * p / 100.0).ceil() as usize).min(population)
+            }
+        }
+    }
+}
+
+/// The same `files` and `seed` always give the same sample, in no particular order
+pub fn pick(mut files: Vec<PathBuf>, size: SampleSize, seed: u64) -> Vec<PathBuf> {
+    files.sort();
+    let n = size.of(files.len());
+    let mut rng = fastrand::Rng::with_seed(seed);
+    // A partial Fisher-Yates shuffle: the first n are a uniform sample
+    for i in 0..n {
+        let j = rng.usize(i..files.len());
+        files.swap(i, j);
+    }
+    files.truncate(n);
+    files
+}
+
+/// A library-wide figure extrapolated from a sample, with its 95% confidence interval
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Estimate {
+    /// The total over `population` files, from the `values` of `sampled` ones. Files
+    /// without a value count as zero. Simple random sampling without replacement, so
+    /// the interval narrows to nothing as the sample approaches the whole library.
+    /// A sample without any spread gives no interval, only its own sum as the lower bound.
+    pub fn total(values: impl IntoIterator<Item = f64>, sampled: usize, population: usize) -> Self {
+        let (mut sum, mut squares, mut count) = (0.0, 0.0, 0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for value in values {
+            sum += value;
+            squares += value * value;
+            count += 1;
+            min = value.min(min);
+            max = value.max(max);
+        }
+        if count < sampled {
+            (min, max) = (min.min(0.0), max.max(0.0));
+        }
+        let (n, big_n) = (sampled as f64, population as f64);
+        if sampled >= population {
+            return Estimate::exact(sum);
+        }
+        let value = big_n * sum / n.max(1.0);
+        if sampled < 2 || min == max {
+            return Estimate {
+                value,
+                low: sum,
+                high: f64::INFINITY,
+            };
+        }
+        let mean = sum / n;
+        let variance = ((squares - sum * mean) / (n - 1.0)).max(0.0);
+        let margin = Z_95 * big_n * (variance / n * (1.0 - n / big_n).max(0.0)).sqrt();
+        Estimate {
+            value,
+            low: (value - margin).max(sum),
+            high: value + margin,
+        }
+    }
+
+    /// How many of `population` files have something, when `hits` of `sampled` ones do.
+    /// A Wilson score interval, which unlike [`Self::total`]'s stays open when every
+    /// sampled file or none has it.
+    pub fn count(hits: usize, sampled: usize, population: usize) -> Self {
+        if sampled >= population {
+            return Estimate::exact(hits as f64);
+        }
+        if sampled == 0 {
+            return Estimate {
+                value: 0.0,
+                low: 0.0,
+                high: f64::INFINITY,
+            };
+        }
+        let (n, big_n) = (sampled as f64, population as f64);
+        let share = hits as f64 / n;
+        // The finite population correction, as a larger sample
+        let n = n * (big_n - 1.0) / (big_n - n);
+        let z2 = Z_95 * Z_95;
+        let center = (share + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin =
+            Z_95 / (1.0 + z2 / n) * (share * (1.0 - share) / n + z2 / (4.0 * n * n)).sqrt();
+        // The unsampled files can't have more or less than all or nothing
+        let unsampled = (population - sampled) as f64;
+        Estimate {
+            value: big_n * share,
+            low: (big_n * (center - margin)).max(hits as f64),
+            high: (big_n * (center + margin)).min(hits as f64 + unsampled),
+        }
+    }
+
+    fn exact(value: f64) -> Self {
+        Estimate {
+            value,
+            low: value,
+            high: value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn total_of_the_whole_library_is_exact() {
+        let estimate = Estimate::total([10.0, 20.0, 30.0], 3, 3);
+        assert_eq!(
+            (estimate.value, estimate.low, estimate.high),
+            (60.0, 60.0, 60.0)
+        );
+    }
+
+    #[test]
+    fn total_extrapolates_with_a_finite_population_correction() {
+        // Mean 20, sample variance 100: 1.96 * 30 * sqrt(100 / 3 * (1 - 3 / 30))
+        let estimate = Estimate::total([10.0, 20.0, 30.0], 3, 30);
+        let margin = Z_95 * 30.0 * 30f64.sqrt();
+        assert!(close(estimate.value, 600.0));
+        assert!(close(estimate.low, 600.0 - margin));
+        assert!(close(estimate.high, 600.0 + margin));
+    }
+
+    #[test]
+    fn total_counts_files_without_a_value_as_zero() {
+        let estimate = Estimate::total([50.0], 2, 4);
+        assert!(close(estimate.value, 100.0));
+    }
+
+    #[test]
+    fn total_is_never_below_what_the_sample_found() {
+        let estimate = Estimate::total([0.0, 0.0, 100.0], 3, 1000);
+        assert_eq!(estimate.low, 100.0);
+        assert!(estimate.high > estimate.value);
+    }
+
+    #[test]
+    fn total_of_a_single_file_has_no_upper_bound() {
+        let estimate = Estimate::total([5.0], 1, 10);
+        assert!(close(estimate.value, 50.0));
+        assert_eq!(estimate.low, 5.0);
+        assert_eq!(estimate.high, f64::INFINITY);
+
+        let nothing = Estimate::total([], 0, 10);
+        assert_eq!((nothing.value, nothing.low), (0.0, 0.0));
+    }
+
+    #[test]
+    fn total_of_a_uniform_sample_has_no_range() {
+        let estimate = Estimate::total([10.0; 5], 5, 47);
+        assert!(close(estimate.value, 470.0));
+        assert_eq!(estimate.low, 50.0);
+        assert_eq!(estimate.high, f64::INFINITY);
+
+        let nothing = Estimate::total([], 5, 47);
+        assert_eq!((nothing.value, nothing.low), (0.0, 0.0));
+        assert_eq!(nothing.high, f64::INFINITY);
+    }
+
+    #[test]
+    fn count_stays_open_when_all_or_none_of_the_sample_hit() {
+        let all = Estimate::count(5, 5, 47);
+        assert_eq!(all.value, 47.0);
+        assert_eq!(all.high, 47.0);
+        assert!(all.low > 5.0 && all.low < 40.0, "{all:?}");
+
+        let none = Estimate::count(0, 5, 47);
+        assert_eq!(none.value, 0.0);
+        assert!(close(none.low, 0.0));
+        assert!(none.high > 7.0 && none.high < 42.0, "{none:?}");
+    }
+
+    #[test]
+    fn count_narrows_with_the_sample() {
+        let small = Estimate::count(10, 20, 1000);
+        let large = Estimate::count(250, 500, 1000);
+        assert!(close(small.value, 500.0) && close(large.value, 500.0));
+        assert!(small.low < small.value && small.value < small.high);
+        assert!(large.high - large.low < small.high - small.low);
+
+        let whole = Estimate::count(7, 10, 10);
+        assert_eq!((whole.value, whole.low, whole.high), (7.0, 7.0, 7.0));
+    }
+
+    #[test]
+    fn pick_is_repeatable_per_seed() {
+        let files = (0..100)
+            .map(|i| PathBuf::from(format!("{i}.mkv")))
+            .collect::<Vec<_>>();
+        let reversed = files.iter().rev().cloned().collect();
+        let sample = pick(files.clone(), SampleSize::Percent(10.0), 7);
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample, pick(reversed, SampleSize::Percent(10.0), 7));
+        assert_ne!(sample, pick(files.clone(), SampleSize::Percent(10.0), 8));
+        assert_eq!(pick(files, SampleSize::Count(500), 7).len(), 100);
+    }
+}
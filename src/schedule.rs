@@ -0,0 +1,272 @@
+use crate::JwatchResult;
+use crate::metastructs::MediaInfo;
+use color_eyre::eyre::bail;
+use indicatif::ProgressBar;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::SystemTime;
+
+/// Why a file is probed, in the order the probes get to them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Class {
+    /// Not in the cache
+    New,
+    /// Cached, but changed since
+    Modified,
+    /// Cached and unchanged as far as we know, least recently checked first
+    Stale,
+    /// Failed to probe last time and unchanged since
+    Retry,
+}
+
+impl Class {
+    const ALL: [Class; 4] = [Class::New, Class::Modified, Class::Stale, Class::Retry];
+
+    fn label(self) -> &'static str {
+        match self {
+            Class::New => "new",
+            Class::Modified => "modified",
+            Class::Stale => "stale",
+            Class::Retry => "retries",
+        }
+    }
+}
+
+/// `--order`: which files of a class go first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Most recently modified
+    Newest,
+    Largest,
+    /// Alphabetical, like the report
+    Path,
+}
+
+impl Order {
+    pub fn from_name(name: &str) -> JwatchResult<Order> {
+        match name {
+            "newest" => Ok(Order::Newest),
+            "largest" => Ok(Order::Largest),
+            "path" => Ok(Order::Path),
+            _ => bail!("unknown order {name:?}, expected one of: newest, largest, path"),
+        }
+    }
+}
+
+/// A file that failed to probe, as it was then
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure {
+    pub mtime: i64,
+    pub size: u64,
+}
+
+impl Failure {
+    pub fn from_metadata(metadata: &fs::Metadata) -> Failure {
+        Failure {
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs() as i64),
+            size: metadata.len(),
+        }
+    }
+}
+
+/// A video or disc title waiting for a probe
+#[derive(Debug)]
+struct Queued {
+    class: Class,
+    /// Higher goes first
+    rank: i64,
+    path: PathBuf,
+    trusted: bool,
+    /// From classifying it, so the probe needs no second stat
+    metadata: Option<fs::Metadata>,
+}
+
+impl Queued {
+    fn key(&self) -> (Reverse<Class>, i64, Reverse<&PathBuf>) {
+        (Reverse(self.class), self.rank, Reverse(&self.path))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// A file handed to a probe: whether it may be served from the cache without a stat,
+/// and its metadata if the queue stat'ed it already
+pub struct Next {
+    pub path: PathBuf,
+    pub trusted: bool,
+    pub metadata: Option<fs::Metadata>,
+}
+
+/// Hands the probes the most interesting file found so far. Files the walk hasn't
+/// reached yet can't jump the queue, but they do overtake everything found before them.
+pub struct ProbeQueue<'a> {
+    order: Order,
+    cache: &'a HashMap<PathBuf, MediaInfo>,
+    failures: &'a HashMap<PathBuf, Failure>,
+    progress: &'a ProgressBar,
+    state: Mutex<QueueState>,
+    /// Signalled when a file is queued, or the queue closed
+    ready: Condvar,
+}
+
+struct QueueState {
+    heap: BinaryHeap<Queued>,
+    /// Nothing more gets queued, the probes quit once the heap is empty
+    closed: bool,
+    remaining: [usize; Class::ALL.len()],
+}
+
+impl<'a> ProbeQueue<'a> {
+    pub fn new(
+        order: Order,
+        cache: &'a HashMap<PathBuf, MediaInfo>,
+        failures: &'a HashMap<PathBuf, Failure>,
+        progress: &'a ProgressBar,
+    ) -> Self {
+        ProbeQueue {
+            order,
+            cache,
+            failures,
+            progress,
+            state: Mutex::new(QueueState {
+                heap: BinaryHeap::new(),
+                closed: false,
+                remaining: [0; Class::ALL.len()],
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Classifies `path`, which costs a stat unless it is `trusted` and cached
+    pub fn push(&self, path: PathBuf, trusted: bool) {
+        let (class, rank, metadata) = self.classify(&path, trusted);
+        let mut state = self.state.lock().unwrap();
+        state.remaining[class as usize] += 1;
+        state.heap.push(Queued {
+            class,
+            rank,
+            path,
+            trusted,
+            metadata,
+        });
+        self.show(&state);
+        self.ready.notify_one();
+    }
+
+    /// Blocks until there is a file to probe, `None` once closed and empty
+    pub fn pop(&self) -> Option<Next> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(queued) = state.heap.pop() {
+                state.remaining[queued.class as usize] -= 1;
+                self.show(&state);
+                return Some(Next {
+                    path: queued.path,
+                    trusted: queued.trusted,
+                    metadata: queued.metadata,
+                });
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+
+    /// What is left, in the order it would have been probed
+    pub fn drain(&self) -> Vec<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        let mut left = vec![];
+        while let Some(queued) = state.heap.pop() {
+            left.push(queued.path);
+        }
+        left
+    }
+
+    /// Class and rank within it, and the metadata if it took a stat
+    fn classify(&self, path: &Path, trusted: bool) -> (Class, i64, Option<fs::Metadata>) {
+        let cached = self.cache.get(path);
+        if trusted && let Some(info) = cached {
+            return (Class::Stale, -info.last_checked.unix_timestamp(), None);
+        }
+        // Unreadable ones fail their probe soon enough
+        let Ok(metadata) = fs::metadata(path) else {
+            return (Class::New, 0, None);
+        };
+        let (class, rank) = self.class_of(path, &metadata);
+        (class, rank, Some(metadata))
+    }
+
+    fn class_of(&self, path: &Path, metadata: &fs::Metadata) -> (Class, i64) {
+        let cached = self.cache.get(path);
+        let failed = self.failures.get(path);
+        let stale = |info: &MediaInfo| (Class::Stale, -info.last_checked.unix_timestamp());
+        let now = Failure::from_metadata(metadata);
+        let rank = match self.order {
+            Order::Newest => now.mtime,
+            Order::Largest => now.size as i64,
+            Order::Path => 0,
+        };
+        // Disc titles only get their real mtime and size from a look inside
+        if metadata.is_dir() {
+            return match (cached, failed) {
+                (Some(info), _) => stale(info),
+                (None, Some(_)) => (Class::Retry, rank),
+                (None, None) => (Class::New, rank),
+            };
+        }
+        match (cached, failed) {
+            (Some(info), _) if info.mtime == now.mtime && info.size as u64 == now.size => {
+                stale(info)
+            }
+            (Some(_), _) => (Class::Modified, rank),
+            (None, Some(failure)) if *failure == now => (Class::Retry, rank),
+            // A new version of a broken file deserves a fresh look
+            (None, Some(_)) => (Class::Modified, rank),
+            (None, None) => (Class::New, rank),
+        }
+    }
+
+    /// Remaining files per class, in the progress bar's prefix
+    fn show(&self, state: &QueueState) {
+        let left = Class::ALL
+            .iter()
+            .filter(|class| state.remaining[**class as usize] > 0)
+            .map(|class| format!("{} {}", state.remaining[*class as usize], class.label()))
+            .collect::<Vec<_>>();
+        self.progress.set_prefix(match left.is_empty() {
+            true => String::new(),
+            false => format!("[{}]", left.join(", ")),
+        });
+    }
+}
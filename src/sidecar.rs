@@ -0,0 +1,293 @@
+use crate::langdetect;
+use crate::language;
+use crate::metastructs::LangTrack;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// External subtitle extensions and the format mediainfo would report for them
+const SUBTITLE_FORMATS: &[(&str, &str)] = &[
+    ("srt", "UTF-8"),
+    ("ass", "ASS"),
+    ("ssa", "SSA"),
+    ("vtt", "WebVTT"),
+    ("sup", "PGS"),
+    ("idx", "VobSub"),
+    ("sub", "VobSub"),
+];
+/// Metadata and artwork written by Kodi, Jellyfin and friends
+const METADATA_EXTENSIONS: &[&str] = &["nfo", "jpg", "jpeg", "png", "webp", "tbn"];
+/// Metadata that belongs to a whole folder (and the seasons below it), not to one video
+const FOLDER_FILE_STEMS: &[&str] = &[
+    "movie",
+    "tvshow",
+    "poster",
+    "folder",
+    "fanart",
+    "banner",
+    "backdrop",
+    "clearart",
+    "clearlogo",
+    "disc",
+    "landscape",
+    "logo",
+    "thumb",
+    "season",
+];
+
+pub fn is_sidecar_file(path: &Path) -> bool {
+    let Some(ext) = path.extension().map(OsStr::to_string_lossy) else {
+        return false;
+    };
+    let ext = ext.to_ascii_lowercase();
+    subtitle_format(&ext).is_some() || METADATA_EXTENSIONS.contains(&ext.as_str())
+}
+
+fn subtitle_format(ext: &str) -> Option<&'static str> {
+    SUBTITLE_FORMATS
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, format)| *format)
+}
+
+/// Sidecars found by the walk, sorted into the video they belong to and orphans
+#[derive(Debug, Default)]
+pub struct Sidecars {
+    subtitles: HashMap<PathBuf, Vec<LangTrack>>,
+    /// Sidecars whose video is gone, with their size
+    pub orphans: Vec<(PathBuf, u64)>,
+}
+
+impl Sidecars {
+    /// A sidecar belongs to the video in its folder whose file stem its name starts with,
+    /// followed by `.` or `-`: `Movie.de.forced.srt`, `Movie-poster.jpg`.
+    /// A disc title's sidecars may also sit inside its folder, named after the folder.
+    /// `detect` guesses the language of untagged text subtitles like for embedded ones.
+    pub fn associate(videos: &[PathBuf], sidecars: Vec<PathBuf>, detect: bool) -> Self {
+        let mut by_dir: HashMap<&Path, Vec<(&PathBuf, Cow<str>)>> = HashMap::new();
+        for video in videos {
+            if let (Some(dir), Some(stem)) = (video.parent(), video.file_stem()) {
+                by_dir
+                    .entry(dir)
+                    .or_default()
+                    .push((video, stem.to_string_lossy()));
+            }
+            // Nothing is ever inside a file, so only disc titles get sidecars this way
+            if let Some(name) = video.file_name() {
+                by_dir
+                    .entry(video)
+                    .or_default()
+                    .push((video, name.to_string_lossy()));
+            }
+        }
+
+        let mut associated = Self::default();
+        for sidecar in sidecars {
+            let Some(name) = sidecar.file_name().map(OsStr::to_string_lossy) else {
+                continue;
+            };
+            let siblings = sidecar
+                .parent()
+                .and_then(|dir| by_dir.get(dir))
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            let owner = siblings
+                .iter()
+                .filter(|(_, stem)| {
+                    name.strip_prefix(stem.as_ref())
+                        .is_some_and(|rest| rest.starts_with(['.', '-']))
+                })
+                .max_by_key(|(_, stem)| stem.len())
+                .map(|(video, stem)| ((*video).clone(), stem.len()));
+
+            let ext = sidecar
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            match owner {
+                Some((video, stem_len)) => {
+                    if let Some(format) = subtitle_format(&ext) {
+                        let tags = &name[stem_len..name.len() - ext.len() - 1];
+                        let track = subtitle_track(&sidecar, tags, format, detect);
+                        associated.subtitles.entry(video).or_default().push(track);
+                    }
+                }
+                None if is_folder_file(&sidecar)
+                    && sidecar
+                        .parent()
+                        .is_some_and(|dir| videos.iter().any(|v| v.starts_with(dir))) => {}
+                None => {
+                    let size = fs::metadata(&sidecar).map(|m| m.len()).unwrap_or(0);
+                    associated.orphans.push((sidecar, size));
+                }
+            }
+        }
+        associated.orphans.sort();
+        associated
+    }
+
+    /// External subtitle tracks of `video`
+    pub fn subtitles(&self, video: &Path) -> &[LangTrack] {
+        self.subtitles.get(video).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// `poster.jpg`, `tvshow.nfo`, `season01-poster.jpg` and the like
+fn is_folder_file(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    ext.is_some_and(|ext| METADATA_EXTENSIONS.contains(&ext.as_str()))
+        && FOLDER_FILE_STEMS.iter().any(|s| stem.starts_with(s))
+}
+
+/// `tags` is what sits between the video stem and the extension, e.g. `.de.forced`
+fn subtitle_track(file: &Path, tags: &str, format: &str, detect: bool) -> LangTrack {
+    let mut track = LangTrack {
+        language: language::UNDETERMINED.to_owned(),
+        size: fs::metadata(file).map(|m| m.len()).unwrap_or(0),
+        format: Some(format.to_owned()),
+        external: true,
+        ..LangTrack::default()
+    };
+    let mut tagged = false;
+    for tag in tags.split(['.', '-']).filter(|t| !t.is_empty()) {
+        match tag.to_ascii_lowercase().as_str() {
+            "forced" | "foreign" => track.forced = true,
+            "sdh" | "cc" => track.sdh = true,
+            // Hindi, unless a language came first: `Movie.en.hi.srt`
+            "hi" if tagged => track.sdh = true,
+            "default" => track.default = true,
+            tag if !tagged => {
+                if let Some(lang) = language::canonical(tag) {
+                    track.language = lang.to_owned();
+                    tagged = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    if detect && track.needs_detection() {
+        let guess = fs::read(file).ok().and_then(|raw| {
+            langdetect::detect(&langdetect::subtitle_text(&String::from_utf8_lossy(&raw)))
+        });
+        track.detected = Some(guess.unwrap_or(language::UNDETERMINED).to_owned());
+    }
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Without detection, only the size needs the file, and missing ones are 0 bytes
+    fn tagged(tags: &str) -> LangTrack {
+        subtitle_track(Path::new("/nonexistent/Movie.srt"), tags, "UTF-8", false)
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn tags_give_language_and_flags() {
+        let track = tagged(".de.forced");
+        assert_eq!(track.language, "de");
+        assert!(track.forced && !track.sdh && track.external);
+
+        let track = tagged(".eng.sdh.default");
+        assert_eq!(track.language, "en");
+        assert!(track.sdh && track.default);
+
+        let track = tagged("-German-foreign");
+        assert_eq!(track.language, "de");
+        assert!(track.forced);
+    }
+
+    #[test]
+    fn hi_after_a_language_means_sdh() {
+        let track = tagged(".en.hi");
+        assert_eq!(track.language, "en");
+        assert!(track.sdh);
+
+        let track = tagged(".hi");
+        assert_eq!(track.language, "hi");
+        assert!(!track.sdh);
+    }
+
+    #[test]
+    fn first_language_wins_and_unknown_tags_are_ignored() {
+        assert_eq!(tagged(".fr.en").language, "fr");
+        assert_eq!(tagged(".1080p.es").language, "es");
+        assert_eq!(tagged("").language, language::UNDETERMINED);
+        assert_eq!(tagged(".final").language, language::UNDETERMINED);
+    }
+
+    #[test]
+    fn sidecars_go_to_the_longest_matching_stem() {
+        let videos = paths(&["/m/Movie.mkv", "/m/Movie Extended.mkv"]);
+        let sidecars = paths(&[
+            "/m/Movie.en.srt",
+            "/m/Movie Extended.de.srt",
+            "/m/Movie-poster.jpg",
+        ]);
+        let associated = Sidecars::associate(&videos, sidecars, false);
+
+        let languages = |video: &str| {
+            associated
+                .subtitles(Path::new(video))
+                .iter()
+                .map(|t| t.language.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(languages("/m/Movie.mkv"), ["en"]);
+        assert_eq!(languages("/m/Movie Extended.mkv"), ["de"]);
+        assert!(associated.orphans.is_empty());
+    }
+
+    #[test]
+    fn orphans_and_folder_files() {
+        let videos = paths(&["/tv/Show/Season 1/Show S01E01.mkv"]);
+        let sidecars = paths(&[
+            "/tv/Show/tvshow.nfo",
+            "/tv/Show/Season 1/season01-poster.jpg",
+            "/tv/Show/Season 1/Show S01E02.en.srt",
+            // Needs a `.` or `-` after the stem
+            "/tv/Show/Season 1/Show S01E01x.en.srt",
+            "/tv/Other/poster.jpg",
+        ]);
+        let associated = Sidecars::associate(&videos, sidecars, false);
+        let orphans = associated
+            .orphans
+            .iter()
+            .map(|(file, _)| file.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            orphans,
+            [
+                "/tv/Other/poster.jpg",
+                "/tv/Show/Season 1/Show S01E01x.en.srt",
+                "/tv/Show/Season 1/Show S01E02.en.srt",
+            ]
+        );
+    }
+
+    #[test]
+    fn disc_titles_own_the_sidecars_inside_them() {
+        let videos = paths(&["/m/Movie (2020)", "/m/Other.mkv"]);
+        let sidecars = paths(&[
+            "/m/Movie (2020)/Movie (2020).en.srt",
+            "/m/Movie (2020).de.srt",
+            "/m/Movie (2020)/poster.jpg",
+        ]);
+        let associated = Sidecars::associate(&videos, sidecars, false);
+        assert_eq!(associated.subtitles(Path::new("/m/Movie (2020)")).len(), 2);
+        assert!(associated.orphans.is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Enough for three MPEG-TS packets, M2TS' bigger ones included
+const SNIFF_LEN: usize = 3 * 192;
+const ASF_GUID: [u8; 16] = [
+    0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C,
+];
+
+/// Container formats recognizable by their first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Matroska,
+    Mp4,
+    Avi,
+    MpegTs,
+    /// MPEG-TS with 4-byte timestamps before each packet, as on Blu-rays
+    M2ts,
+    MpegPs,
+    Asf,
+    Flv,
+    Ogg,
+}
+
+impl Container {
+    /// Extensions files in this container usually carry
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Container::Matroska => &["mkv", "webm", "mk3d"],
+            Container::Mp4 => &["mp4", "m4v", "mov", "3gp", "3g2"],
+            Container::Avi => &["avi"],
+            Container::MpegTs => &["ts", "mts", "m2ts"],
+            Container::M2ts => &["m2ts", "mts"],
+            Container::MpegPs => &["mpg", "mpeg", "vob"],
+            Container::Asf => &["wmv", "asf"],
+            Container::Flv => &["flv"],
+            Container::Ogg => &["ogv", "ogg"],
+        }
+    }
+
+    pub fn matches_extension(self, path: &Path) -> bool {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .is_some_and(|ext| self.extensions().contains(&ext.as_str()))
+    }
+}
+
+impl Display for Container {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Container::Matroska => "Matroska",
+            Container::Mp4 => "MP4/QuickTime",
+            Container::Avi => "AVI",
+            Container::MpegTs => "MPEG-TS",
+            Container::M2ts => "M2TS",
+            Container::MpegPs => "MPEG-PS",
+            Container::Asf => "ASF",
+            Container::Flv => "FLV",
+            Container::Ogg => "Ogg",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Reads the first bytes of the file, `None` if they aren't a known video container
+pub fn sniff(path: &Path) -> std::io::Result<Option<Container>> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    Ok(detect(&head))
+}
+
+fn detect(head: &[u8]) -> Option<Container> {
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    // Sync bytes at every packet start, one match alone is too likely by chance
+    let synced = |first: usize, packet: usize| (0..3).all(|i| at(first + i * packet, &[0x47]));
+
+    if at(0, &[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some(Container::Matroska)
+    } else if at(4, b"ftyp") || at(4, b"moov") || at(4, b"mdat") || at(4, b"wide") {
+        Some(Container::Mp4)
+    } else if at(0, b"RIFF") && at(8, b"AVI ") {
+        Some(Container::Avi)
+    } else if at(0, &ASF_GUID) {
+        Some(Container::Asf)
+    } else if at(0, b"FLV") {
+        Some(Container::Flv)
+    } else if at(0, b"OggS") {
+        Some(Container::Ogg)
+    } else if at(0, &[0x00, 0x00, 0x01, 0xBA]) {
+        Some(Container::MpegPs)
+    } else if synced(0, 188) {
+        Some(Container::MpegTs)
+    } else if synced(4, 192) {
+        Some(Container::M2ts)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(at: usize, magic: &[u8]) -> Vec<u8> {
+        let mut head = vec![0; SNIFF_LEN];
+        head[at..at + magic.len()].copy_from_slice(magic);
+        head
+    }
+
+    /// Sync bytes at the start of three packets
+    fn packets(first: usize, packet: usize) -> Vec<u8> {
+        let mut head = vec![0xFF; SNIFF_LEN];
+        for i in 0..3 {
+            head[first + i * packet] = 0x47;
+        }
+        head
+    }
+
+    #[test]
+    fn detects_containers_by_magic() {
+        let cases: &[(usize, &[u8], Container)] = &[
+            (0, &[0x1A, 0x45, 0xDF, 0xA3], Container::Matroska),
+            (4, b"ftypisom", Container::Mp4),
+            (4, b"moov", Container::Mp4),
+            (0, b"FLV\x01", Container::Flv),
+            (0, b"OggS", Container::Ogg),
+            (0, &[0x00, 0x00, 0x01, 0xBA], Container::MpegPs),
+            (0, &ASF_GUID, Container::Asf),
+        ];
+        for (at, magic, container) in cases {
+            assert_eq!(detect(&head(*at, magic)), Some(*container), "{magic:?}");
+        }
+
+        let mut avi = head(0, b"RIFF");
+        avi[8..12].copy_from_slice(b"AVI ");
+        assert_eq!(detect(&avi), Some(Container::Avi));
+        // Also RIFF, but audio
+        let mut wav = head(0, b"RIFF");
+        wav[8..12].copy_from_slice(b"WAVE");
+        assert_eq!(detect(&wav), None);
+    }
+
+    #[test]
+    fn detects_transport_streams_by_packet_sync() {
+        assert_eq!(detect(&packets(0, 188)), Some(Container::MpegTs));
+        assert_eq!(detect(&packets(4, 192)), Some(Container::M2ts));
+
+        // One sync byte is no transport stream
+        assert_eq!(detect(&head(0, &[0x47])), None);
+        let mut broken = packets(0, 188);
+        broken[2 * 188] = 0;
+        assert_eq!(detect(&broken), None);
+    }
+
+    #[test]
+    fn short_or_unknown_heads() {
+        assert_eq!(detect(&[]), None);
+        assert_eq!(detect(&[0x1A, 0x45, 0xDF]), None);
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n"), None);
+        // Too short to hold the three packets
+        assert_eq!(detect(&packets(0, 188)[..2 * 188]), None);
+    }
+}
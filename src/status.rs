@@ -0,0 +1,167 @@
+use crate::cachedb::CacheDB;
+use crate::rules::Rule;
+use crate::{
+    ExitStatus, JwatchResult, ProbeOutcome, RunOutcome, Settings, probe_progress_bar,
+    walk_and_probe,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `git status` for the library: what changed on disk and in the findings since the last scan.
+/// New and modified files are probed but deliberately not stored, so the next scan still sees them.
+/// Findings never fail the run here.
+pub fn status(
+    settings: &Settings,
+    cachedb: &CacheDB,
+    interrupted: &Arc<AtomicBool>,
+) -> JwatchResult<RunOutcome> {
+    let progress = probe_progress_bar()?;
+    let (cache, dirs, failures, recorded, cached_roots) = cachedb
+        .load_all()
+        .and_then(|cache| {
+            Ok((
+                cache,
+                cachedb.load_dirs()?,
+                cachedb.load_failures()?,
+                cachedb.load_findings()?,
+                cachedb.load_roots()?,
+            ))
+        })
+        .unwrap_or_else(|e| ExitStatus::CacheDb.fail(e));
+
+    let mut outcome = RunOutcome::default();
+    let mut new = vec![];
+    let mut modified = vec![];
+    let mut in_progress = vec![];
+    // Keyed by full path, file names repeat across folders
+    let mut appeared: BTreeMap<PathBuf, Vec<(Rule, String)>> = BTreeMap::new();
+    let mut passed: BTreeMap<PathBuf, Vec<Rule>> = BTreeMap::new();
+    let mut probed = vec![];
+
+    let library = walk_and_probe(
+        settings,
+        &cache,
+        &dirs,
+        &failures,
+        None,
+        interrupted,
+        &progress,
+        |file, probe| {
+            let info = match probe {
+                ProbeOutcome::Skipped => return,
+                ProbeOutcome::InProgress => {
+                    in_progress.push(file.to_path_buf());
+                    return;
+                }
+                ProbeOutcome::Cached(info) | ProbeOutcome::Updated(info) => info,
+                ProbeOutcome::Fresh(info) => {
+                    if cache.contains_key(file) {
+                        modified.push(file.to_path_buf());
+                    } else {
+                        new.push(file.to_path_buf());
+                    }
+                    info
+                }
+                ProbeOutcome::Failed(e, _) => {
+                    progress.println(format!("{:?}: {}", e, file.display()));
+                    outcome.errors += 1;
+                    return;
+                }
+            };
+            probed.push((file.to_path_buf(), info));
+        },
+    )?;
+    progress.finish_using_style();
+
+    // Sidecars and policy layers are only complete after the walk
+    for (file, info) in &probed {
+        let before = recorded.get(file).map(Vec::as_slice).unwrap_or(&[]);
+        let now = settings.evaluate(file, info, &library);
+        for finding in &now {
+            if !before.iter().any(|(rule, _)| *rule == finding.rule) {
+                appeared
+                    .entry(file.clone())
+                    .or_default()
+                    .push((finding.rule, finding.reason.clone()));
+            }
+        }
+        for (rule, _) in before {
+            if !now.iter().any(|f| f.rule == *rule) {
+                passed.entry(file.clone()).or_default().push(*rule);
+            }
+        }
+    }
+
+    let seen = library.files.iter().collect::<HashSet<_>>();
+    // Files cached from folders not scanned now are none of our business
+    let scanned_root = |file: &PathBuf| {
+        cached_roots
+            .get(file)
+            .is_some_and(|root| settings.roots.iter().any(|r| &r.path == root))
+    };
+    // An interrupted walk hasn't seen everything, what it missed isn't gone
+    let interrupted = interrupted.load(Ordering::Relaxed);
+    let mut removed = match interrupted {
+        true => vec![],
+        false => cache
+            .keys()
+            .filter(|file| !seen.contains(file) && scanned_root(file))
+            .collect::<Vec<_>>(),
+    };
+    removed.sort();
+    new.sort();
+    modified.sort();
+    in_progress.sort();
+
+    if interrupted {
+        println!("Status interrupted, results are partial and removed files not checked");
+    }
+    if new.is_empty()
+        && modified.is_empty()
+        && in_progress.is_empty()
+        && removed.is_empty()
+        && appeared.is_empty()
+        && passed.is_empty()
+    {
+        println!("Nothing changed since the last scan");
+        return Ok(outcome);
+    }
+
+    if !new.is_empty() || !modified.is_empty() || !in_progress.is_empty() || !removed.is_empty() {
+        println!("Changes since the last scan:");
+        for file in new {
+            println!("\tnew:      {}", file.display());
+        }
+        for file in modified {
+            println!("\tmodified: {}", file.display());
+        }
+        for file in in_progress {
+            println!("\tin progress: {}", file.display());
+        }
+        for file in removed {
+            println!("\tremoved:  {}", file.display());
+        }
+    }
+
+    if !appeared.is_empty() || !passed.is_empty() {
+        println!("Findings changed since the last scan:");
+        for (file, rules) in passed {
+            for rule in rules {
+                println!("\t{}: now passes {rule}", file.display());
+            }
+        }
+        for (file, findings) in appeared {
+            for (rule, reason) in findings {
+                println!(
+                    "\t{}: newly has {} ({reason})",
+                    file.display(),
+                    rule.description()
+                );
+            }
+        }
+    }
+
+    Ok(outcome)
+}
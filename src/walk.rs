@@ -0,0 +1,276 @@
+use crate::exclude::{IGNORE_FILE_NAME, Ignores};
+use crate::{JwatchResult, Root};
+use color_eyre::eyre::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Directories modified this recently may change again within the timestamp granularity
+/// of coarse filesystems (FAT, some NAS shares), their listing isn't worth remembering
+const RACY_MTIME: Duration = Duration::from_secs(2);
+
+/// A directory's entries as of its mtime, cached so an unchanged directory needs no listing
+#[derive(Debug, Clone)]
+pub struct DirListing {
+    /// Nanoseconds since the epoch
+    pub mtime: i64,
+    /// When the directory was last listed and its files stat'ed, seconds since the epoch
+    pub verified: i64,
+    pub entries: Vec<DirEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// Always UTF-8 in cached listings
+    pub name: OsString,
+    pub dir: bool,
+}
+
+/// A directory an interrupted walk didn't get to
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingDir {
+    pub dir: PathBuf,
+    /// Index into the roots
+    pub root: usize,
+    /// Directories above with an ignore file, outermost first
+    pub ignore_dirs: Vec<PathBuf>,
+    pub trusted: bool,
+}
+
+/// Told about everything the walk finds, from several threads at once
+pub trait Visitor: Sync {
+    /// Whether to descend into `dir`. `trusted` when its parent's listing came from the cache.
+    fn dir(&self, dir: &Path, entries: &[DirEntry], trusted: bool) -> JwatchResult<bool>;
+    /// `trusted` when the listing it was in came from the cache: it is unlikely to have
+    /// changed since it was cached, and not worth a stat
+    fn file(&self, file: PathBuf, trusted: bool) -> JwatchResult<()>;
+    /// A directory listed from disk, to be cached for the next walk
+    fn listed(&self, dir: PathBuf, listing: DirListing);
+}
+
+/// Walks all roots on `jobs` threads. Directories whose mtime matches `cached` are not
+/// listed again, unless verified longer than `verify_after` ago: their mtime only changes
+/// when entries come and go, files rewritten in place need a full look now and then.
+/// Prunes `--exclude` globs and `.jwatchignore` files, roots inside other roots are
+/// walked on their own only. Stops early on the first error, or on interrupt, returning
+/// the directories not listed yet. `resume` starts from those instead of the roots.
+pub fn walk(
+    roots: &[Root],
+    resume: Option<&[PendingDir]>,
+    jobs: usize,
+    cached: &HashMap<PathBuf, DirListing>,
+    verify_after: Duration,
+    interrupted: &AtomicBool,
+    visitor: &impl Visitor,
+) -> JwatchResult<Vec<PendingDir>> {
+    let work = match resume {
+        Some(pending) => pending
+            .iter()
+            .map(|p| {
+                Ok(Work {
+                    dir: p.dir.clone(),
+                    root: p.root,
+                    ignores: Ignores::from_dirs(&p.ignore_dirs)?,
+                    trusted: p.trusted,
+                })
+            })
+            .collect::<JwatchResult<_>>()?,
+        None => roots
+            .iter()
+            .enumerate()
+            .map(|(root, r)| Work {
+                dir: r.path.clone(),
+                root,
+                ignores: Ignores::default(),
+                trusted: false,
+            })
+            .collect(),
+    };
+    let walker = Walker {
+        roots,
+        cached,
+        verify_after,
+        now: SystemTime::now(),
+        queue: Mutex::new(Queue {
+            work,
+            busy: 0,
+            error: None,
+        }),
+        ready: Condvar::new(),
+    };
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| walker.work(interrupted, visitor));
+        }
+    });
+    let queue = walker.queue.into_inner().unwrap();
+    if let Some(e) = queue.error {
+        return Err(e);
+    }
+    Ok(queue
+        .work
+        .into_iter()
+        .map(|work| PendingDir {
+            ignore_dirs: work.ignores.dirs(),
+            dir: work.dir,
+            root: work.root,
+            trusted: work.trusted,
+        })
+        .collect())
+}
+
+struct Walker<'a> {
+    roots: &'a [Root],
+    cached: &'a HashMap<PathBuf, DirListing>,
+    verify_after: Duration,
+    now: SystemTime,
+    queue: Mutex<Queue>,
+    /// Signalled when work is queued, or the last busy thread finishes
+    ready: Condvar,
+}
+
+struct Queue {
+    work: VecDeque<Work>,
+    /// Threads listing a directory, which may queue more
+    busy: usize,
+    error: Option<color_eyre::Report>,
+}
+
+/// A directory still to list
+struct Work {
+    dir: PathBuf,
+    /// Index into the roots
+    root: usize,
+    /// Those of the directories above
+    ignores: Ignores,
+    trusted: bool,
+}
+
+impl Walker<'_> {
+    fn work(&self, interrupted: &AtomicBool, visitor: &impl Visitor) {
+        loop {
+            let work = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    if interrupted.load(Ordering::Relaxed) || queue.error.is_some() {
+                        return;
+                    }
+                    if let Some(work) = queue.work.pop_front() {
+                        queue.busy += 1;
+                        break work;
+                    }
+                    if queue.busy == 0 {
+                        return;
+                    }
+                    queue = self.ready.wait(queue).unwrap();
+                }
+            };
+
+            let found = self.list(&work, visitor);
+            let mut queue = self.queue.lock().unwrap();
+            queue.busy -= 1;
+            match found {
+                Ok(found) => queue.work.extend(found),
+                Err(e) => {
+                    queue.error.get_or_insert(e);
+                }
+            }
+            // Idle threads either pick up the new work, or quit once nothing is busy
+            self.ready.notify_all();
+        }
+    }
+
+    /// Visits the directory's entries, returning the subdirectories to walk
+    fn list(&self, work: &Work, visitor: &impl Visitor) -> JwatchResult<Vec<Work>> {
+        let dir = &work.dir;
+        let mtime = fs::metadata(dir)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("cannot read {}", dir.display()))?;
+        let mtime_nanos = nanos(mtime);
+        let verified = |listing: &DirListing| {
+            secs(self.now).saturating_sub(listing.verified) < self.verify_after.as_secs() as i64
+        };
+        let (entries, from_cache) = match self.cached.get(dir) {
+            Some(listing) if listing.mtime == mtime_nanos && verified(listing) => {
+                (listing.entries.clone(), true)
+            }
+            _ => {
+                let entries = read_dir(dir)?;
+                let racy = self.now.duration_since(mtime).unwrap_or_default() < RACY_MTIME;
+                // The cache stores names as text
+                if !racy && entries.iter().all(|e| e.name.to_str().is_some()) {
+                    visitor.listed(
+                        dir.clone(),
+                        DirListing {
+                            mtime: mtime_nanos,
+                            verified: secs(self.now),
+                            entries: entries.clone(),
+                        },
+                    );
+                }
+                (entries, false)
+            }
+        };
+
+        if !visitor.dir(dir, &entries, work.trusted)? {
+            return Ok(vec![]);
+        }
+
+        let mut ignores = work.ignores.clone();
+        if entries.iter().any(|e| !e.dir && e.name == IGNORE_FILE_NAME) {
+            ignores.push(dir)?;
+        }
+        let root = &self.roots[work.root];
+        let mut subdirs = vec![];
+        for entry in entries {
+            let path = dir.join(&entry.name);
+            // --exclude can't be overridden by ignore files
+            if root.excludes.matched(&path, entry.dir).is_ignore()
+                || ignores.is_ignored(&path, entry.dir)
+            {
+                continue;
+            }
+            if !entry.dir {
+                visitor.file(path, from_cache)?;
+            } else if !self.roots.iter().any(|r| r.path == path) {
+                subdirs.push(Work {
+                    dir: path,
+                    root: work.root,
+                    ignores: ignores.clone(),
+                    trusted: from_cache,
+                });
+            }
+        }
+        Ok(subdirs)
+    }
+}
+
+fn read_dir(dir: &Path) -> JwatchResult<Vec<DirEntry>> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("cannot list {}", dir.display()))? {
+        let entry = entry?;
+        entries.push(DirEntry {
+            name: entry.file_name(),
+            // Symlinked folders aren't followed, symlinked files are probed through the link
+            dir: entry.file_type()?.is_dir(),
+        });
+    }
+    Ok(entries)
+}
+
+fn nanos(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i64
+}
+
+fn secs(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
@@ -0,0 +1,338 @@
+use crate::argparse::Args;
+use crate::cachedb::CacheDB;
+use crate::config::{Config, LAYER_FILE_NAME, PolicyLayers};
+use crate::exclude::{IGNORE_FILE_NAME, Ignores};
+use crate::metastructs::MediaInfo;
+use crate::sidecar::{self, Sidecars};
+use crate::{
+    ExitStatus, JwatchResult, Library, ProbeOutcome, Root, RunOutcome, Settings, disc,
+    is_video_file, probe_one, scan, sniff,
+};
+use indicatif::ProgressBar;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use signal_hook::consts::{SIGHUP, SIGTERM};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// A file is worth a probe once its size hasn't changed for this long, and it is older
+/// than `--settle-secs`
+const SETTLE: Duration = Duration::from_secs(5);
+/// How often sizes of pending files are checked
+const TICK: Duration = Duration::from_secs(1);
+
+/// Scans once to catch up, then probes videos as they arrive or change and prints their
+/// findings right away. Runs until SIGTERM or CTRL+C, a second one force quits.
+/// SIGHUP re-reads the config, a broken one is reported and the old one kept.
+pub fn watch(
+    args: &Args,
+    settings: Settings,
+    cachedb: &CacheDB,
+    interrupted: &Arc<AtomicBool>,
+) -> JwatchResult<RunOutcome> {
+    // Registered before the flag itself, so it only fires on a second SIGTERM
+    signal_hook::flag::register_conditional_shutdown(
+        SIGTERM,
+        ExitStatus::Interrupted as i32,
+        interrupted.clone(),
+    )?;
+    signal_hook::flag::register(SIGTERM, interrupted.clone())?;
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, reload.clone())?;
+
+    let caught_up = scan(&settings, cachedb, interrupted)?;
+    let mut outcome = RunOutcome {
+        db_errors: caught_up.db_errors,
+        stopped: true,
+        ..RunOutcome::default()
+    };
+    cachedb
+        .flush()
+        .unwrap_or_else(|e| ExitStatus::CacheDb.fail(e));
+    let mut cache = cachedb
+        .load_all()
+        .unwrap_or_else(|e| ExitStatus::CacheDb.fail(e));
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mut settings = settings;
+    watch_roots(&mut watcher, &[], &settings.roots)?;
+
+    let progress = ProgressBar::hidden();
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    while !interrupted.load(Ordering::Relaxed) {
+        if reload.swap(false, Ordering::Relaxed) {
+            match Settings::resolve(args) {
+                Ok(reloaded) => {
+                    watch_roots(&mut watcher, &settings.roots, &reloaded.roots)?;
+                    settings = reloaded;
+                    // Still settling or in progress, unless newly excluded or outside the roots
+                    pending.retain(|_, file| target(&settings, &file.watched).is_some());
+                    println!("Reloaded the config");
+                }
+                Err(e) => eprintln!("Keeping the old config: {e:?}"),
+            }
+        }
+
+        let first = match rx.recv_timeout(TICK) {
+            Ok(event) => Some(event),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        for event in first.into_iter().chain(rx.try_iter()) {
+            match event {
+                Ok(event) => noticed(&settings, &event, &mut pending),
+                Err(e) => eprintln!("watch: {e}"),
+            }
+        }
+
+        let now = Instant::now();
+        let mut settled = HashMap::new();
+        pending.retain(|target, file| {
+            // Gone again, a rename or temporary file
+            let Ok(size) = fs::metadata(&file.watched).map(|m| m.len()) else {
+                return false;
+            };
+            if file.size != Some(size) {
+                file.size = Some(size);
+                file.since = now;
+                return true;
+            }
+            if now.duration_since(file.since) < SETTLE {
+                return true;
+            }
+            settled.insert(target.clone(), file.watched.clone());
+            false
+        });
+        let mut settled = settled.into_iter().collect::<Vec<_>>();
+        settled.sort();
+        for (target, watched) in settled {
+            let done = arrived(
+                &target,
+                &settings,
+                cachedb,
+                &mut cache,
+                &progress,
+                &mut outcome,
+            );
+            if !done {
+                // Checked again next tick, until it is older than --settle-secs
+                pending.insert(
+                    target,
+                    Pending {
+                        size: fs::metadata(&watched).map(|m| m.len()).ok(),
+                        watched,
+                        since: now,
+                    },
+                );
+            }
+        }
+    }
+    println!("Stopped watching");
+    Ok(outcome)
+}
+
+/// A video, or disc title, waiting for its size to settle
+struct Pending {
+    /// The file that changed, for discs one inside the structure
+    watched: PathBuf,
+    size: Option<u64>,
+    /// Last size change
+    since: Instant,
+}
+
+fn watch_roots(watcher: &mut RecommendedWatcher, old: &[Root], new: &[Root]) -> JwatchResult<()> {
+    let old = old.iter().map(|r| &r.path).collect::<HashSet<_>>();
+    let new = new.iter().map(|r| &r.path).collect::<HashSet<_>>();
+    for root in old.difference(&new) {
+        watcher.unwatch(root)?;
+    }
+    for root in new.difference(&old) {
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        println!("Watching {}", root.display());
+    }
+    Ok(())
+}
+
+/// Queues the videos an event touched. Folders moved in are looked through, their files
+/// may have arrived before the watch on them did.
+fn noticed(settings: &Settings, event: &Event, pending: &mut HashMap<PathBuf, Pending>) {
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        return;
+    }
+    let mut queue = |path: &Path| {
+        let Some(target) = target(settings, path) else {
+            return;
+        };
+        pending.entry(target).or_insert_with(|| Pending {
+            watched: path.to_path_buf(),
+            size: None,
+            since: Instant::now(),
+        });
+    };
+    for path in &event.paths {
+        if path.is_dir() {
+            WalkDir::new(path)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file())
+                .for_each(|e| queue(e.path()));
+        } else {
+            queue(path);
+        }
+    }
+}
+
+/// What to probe when `path` changes: itself, the title of the disc it belongs to,
+/// or nothing for non-videos and ignored paths
+fn target(settings: &Settings, path: &Path) -> Option<PathBuf> {
+    let root = settings.root_of(path);
+    match is_ignored(root, path) {
+        Ok(false) => {}
+        Ok(true) => return None,
+        Err(e) => {
+            eprintln!("{e:?}");
+            return None;
+        }
+    }
+    if let Some(title) = path
+        .ancestors()
+        .take_while(|dir| *dir != root.path)
+        .find_map(disc::title_dir)
+    {
+        return Some(title.to_path_buf());
+    }
+    // Sniffed once settled, the first bytes may not be written yet
+    let candidate = is_video_file(path) || (settings.sniff && !sidecar::is_sidecar_file(path));
+    candidate.then(|| path.to_path_buf())
+}
+
+/// `--exclude` and the `.jwatchignore` files from the root down, like the walk applies them
+fn is_ignored(root: &Root, path: &Path) -> JwatchResult<bool> {
+    let Ok(relative) = path.strip_prefix(&root.path) else {
+        return Ok(true);
+    };
+    let mut ignores = Ignores::default();
+    let mut current = root.path.clone();
+    for component in relative.components() {
+        if current.join(IGNORE_FILE_NAME).is_file() {
+            ignores.push(&current)?;
+        }
+        current.push(component);
+        let is_dir = current != path || path.is_dir();
+        if root.excludes.matched(&current, is_dir).is_ignore()
+            || ignores.is_ignored(&current, is_dir)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Probes, stores and reports a settled video. False if it turned out to be still
+/// in progress after all.
+fn arrived(
+    target: &Path,
+    settings: &Settings,
+    cachedb: &CacheDB,
+    cache: &mut HashMap<PathBuf, MediaInfo>,
+    progress: &ProgressBar,
+    outcome: &mut RunOutcome,
+) -> bool {
+    if !target.is_dir() && !is_video_file(target) && !matches!(sniff::sniff(target), Ok(Some(_))) {
+        return true;
+    }
+    let info = match probe_one(target, false, None, cache, settings, progress) {
+        ProbeOutcome::Skipped | ProbeOutcome::Cached(_) => return true,
+        ProbeOutcome::InProgress => return false,
+        ProbeOutcome::Fresh(info) | ProbeOutcome::Updated(info) => info,
+        ProbeOutcome::Failed(e, _) => {
+            eprintln!("{:?}: {}", e, target.display());
+            return true;
+        }
+    };
+    let root = &settings.root_of(target).path;
+    let library = match library_around(settings, target) {
+        Ok(library) => library,
+        Err(e) => {
+            eprintln!("{:?}: {}", e, target.display());
+            return true;
+        }
+    };
+    let findings = settings.evaluate(target, &info, &library);
+    let stored = cachedb
+        .store_to_cachedb(target, root, &info)
+        .and_then(|()| cachedb.store_findings(target, &findings))
+        .and_then(|()| cachedb.flush());
+    if let Err(e) = stored {
+        eprintln!("cachedb: {:?}: {}", e, target.display());
+        outcome.db_errors += 1;
+    }
+    cache.insert(target.to_path_buf(), info);
+
+    let filename = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let key = settings.baseline_key(target);
+    let mut reported = 0;
+    for finding in findings {
+        if settings
+            .baseline
+            .as_ref()
+            .is_some_and(|known| known.contains(&key, finding.rule))
+        {
+            continue;
+        }
+        println!("{} found in: {filename}", finding.reason);
+        reported += 1;
+    }
+    if reported == 0 {
+        println!("No findings in: {filename}");
+    }
+    true
+}
+
+/// Sidecars and policy layers for a single video, from its folder and the ones above
+fn library_around(settings: &Settings, file: &Path) -> JwatchResult<Library> {
+    let root = &settings.root_of(file).path;
+    let mut layers = PolicyLayers::default();
+    for dir in file.ancestors().skip(1) {
+        let layer = dir.join(LAYER_FILE_NAME);
+        if layer.is_file() {
+            layers.insert(dir.to_path_buf(), Config::load_layer(&layer)?);
+        }
+        if dir == root {
+            break;
+        }
+    }
+
+    let mut videos = vec![file.to_path_buf()];
+    let mut sidecar_files = vec![];
+    if let Some(dir) = file.parent() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path == file {
+                continue;
+            }
+            if is_video_file(&path) {
+                videos.push(path);
+            } else if sidecar::is_sidecar_file(&path) {
+                sidecar_files.push(path);
+            }
+        }
+    }
+    let sidecars = Sidecars::associate(&videos, sidecar_files, settings.detect_subtitles);
+    Ok(Library {
+        files: videos,
+        sidecars,
+        layers,
+        listings: vec![],
+        walked: HashSet::new(),
+        unfinished: None,
+    })
+}